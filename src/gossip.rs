@@ -1,7 +1,11 @@
 use crate::{
     Subscription, SubscriptionError, SubscriptionSlice, Subscriptions, SubscriptionsSlice,
 };
-use keynesis::{key::ed25519, passport::block::Time};
+use keynesis::{
+    hash::{Blake2b, Digest as _},
+    key::ed25519,
+    passport::block::Time,
+};
 use std::{
     convert::TryInto as _,
     fmt::{self, Formatter},
@@ -22,8 +26,13 @@ const IPV4_END: usize = IPV4_INDEX + 4;
 const IPV6_INDEX: usize = TIME_END;
 const IPV6_END: usize = IPV6_INDEX + 16;
 
+const PORT_SIZE: usize = std::mem::size_of::<u16>();
+
+const METADATA_LEN_SIZE: usize = std::mem::size_of::<u16>();
+
+/// the header of the gossip and the size of the metadata (if any)
 #[derive(Copy, Clone, Eq, PartialEq, Hash)]
-struct GossipInfo(u16);
+struct GossipInfo(u16, usize);
 
 #[derive(Clone, Eq, PartialEq, Hash)]
 pub struct Gossip(Vec<u8>);
@@ -33,12 +42,21 @@ pub struct GossipSlice<'a>(&'a [u8]);
 
 #[derive(Debug, Error)]
 pub enum GossipError {
-    #[error("Invalid gossip size, expected at least {min}")]
+    #[error("Invalid gossip size, expected {}", SizeBounds(*.min, *.max))]
     InvalidSize { min: usize, max: Option<usize> },
 
     #[error("The signature does not match the public key and the content")]
     InvalidSignature,
 
+    #[error("The secret key does not match the gossip's public key")]
+    InvalidSecretKey,
+
+    #[error(
+        "The metadata is too large ({size} bytes), expected at most {}",
+        Gossip::MAX_METADATA_SIZE
+    )]
+    MetadataTooLarge { size: usize },
+
     #[error("Invalid subscription ({index}): {error}")]
     InvalidSubscription {
         index: usize,
@@ -46,6 +64,19 @@ pub enum GossipError {
     },
 }
 
+/// display the expected size bounds of a gossip
+struct SizeBounds(usize, Option<usize>);
+
+impl fmt::Display for SizeBounds {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self(min, None) => write!(f, "at least {}", min),
+            Self(min, Some(max)) if min == max => write!(f, "exactly {}", min),
+            Self(min, Some(max)) => write!(f, "between {} and {}", min, max),
+        }
+    }
+}
+
 impl GossipInfo {
     const SIZE: usize = std::mem::size_of::<u16>();
 
@@ -62,7 +93,29 @@ impl GossipInfo {
                 .try_into()
                 .expect("valid 2 bytes on the slice"),
         );
-        Ok(Self(sub))
+        let mut info = Self(sub, 0);
+
+        if info.has_metadata() {
+            let start = info.metadata_start();
+            let end = start + METADATA_LEN_SIZE;
+            if slice.len() < end {
+                return Err(GossipError::InvalidSize {
+                    min: end,
+                    max: None,
+                });
+            }
+            let size = u16::from_be_bytes(
+                slice[start..end]
+                    .try_into()
+                    .expect("valid 2 bytes on the slice"),
+            ) as usize;
+            if size > Gossip::MAX_METADATA_SIZE {
+                return Err(GossipError::MetadataTooLarge { size });
+            }
+            info.1 = size;
+        }
+
+        Ok(info)
     }
 
     fn set_num_subscriptions(&mut self, num: usize) {
@@ -79,6 +132,16 @@ impl GossipInfo {
         self.0 &= 0b0111_1111_1111_1111;
     }
 
+    fn set_metadata(&mut self, size: usize) {
+        self.0 |= 0b0100_0000_0000_0000;
+        self.1 = size;
+    }
+
+    #[inline(always)]
+    fn has_metadata(&self) -> bool {
+        self.0 & 0b0100_0000_0000_0000 == 0b0100_0000_0000_0000
+    }
+
     #[inline(always)]
     fn is_ipv4(&self) -> bool {
         self.0 & 0b1000_0000_0000_0000 == 0b1000_0000_0000_0000
@@ -113,7 +176,7 @@ impl GossipInfo {
 
     #[inline(always)]
     fn port_end(&self) -> usize {
-        self.port_start() + 2
+        self.port_start() + PORT_SIZE
     }
 
     #[inline(always)]
@@ -127,10 +190,30 @@ impl GossipInfo {
     }
 
     #[inline(always)]
-    fn signature_start(&self) -> usize {
+    fn metadata_start(&self) -> usize {
         self.subscription_end()
     }
 
+    /// the metadata without their length prefix
+    #[inline(always)]
+    fn metadata_content_start(&self) -> usize {
+        self.metadata_start() + METADATA_LEN_SIZE
+    }
+
+    #[inline(always)]
+    fn metadata_end(&self) -> usize {
+        if self.has_metadata() {
+            self.metadata_content_start() + self.1
+        } else {
+            self.metadata_start()
+        }
+    }
+
+    #[inline(always)]
+    fn signature_start(&self) -> usize {
+        self.metadata_end()
+    }
+
     #[inline(always)]
     fn signature_end(&self) -> usize {
         self.signature_start() + ed25519::Signature::SIZE
@@ -141,8 +224,31 @@ impl Gossip {
     pub const MAX_NUM_SUBSCRIPTIONS: usize = Subscriptions::MAX_NUM_SUBSCRIPTIONS;
     pub const MIN_SIZE: usize =
         IPV4_END + ed25519::Signature::SIZE + Self::MAX_NUM_SUBSCRIPTIONS * Subscription::SIZE;
-    pub const MAX_SIZE: usize =
-        IPV6_END + ed25519::Signature::SIZE + Self::MAX_NUM_SUBSCRIPTIONS * Subscription::SIZE;
+    /// maximum size of a gossip, port and metadata included
+    pub const MAX_SIZE: usize = IPV6_END
+        + PORT_SIZE
+        + ed25519::Signature::SIZE
+        + Self::MAX_NUM_SUBSCRIPTIONS * Subscription::SIZE
+        + METADATA_LEN_SIZE
+        + Self::MAX_METADATA_SIZE;
+    /// maximum size of the metadata, see [`Gossip::new_with_metadata`]
+    pub const MAX_METADATA_SIZE: usize = 256;
+
+    /// size in bytes of a gossip without metadata for the given address
+    /// and number of subscriptions
+    ///
+    /// the metadata (see [`Gossip::new_with_metadata`]) take 2 more bytes
+    /// plus their length
+    pub fn size_for(address: SocketAddr, num_subscriptions: usize) -> usize {
+        let mut info = GossipInfo(0, 0);
+        info.set_num_subscriptions(num_subscriptions);
+        if address.is_ipv4() {
+            info.set_ipv4()
+        } else {
+            info.set_ipv6()
+        }
+        info.signature_end()
+    }
 
     /// prepare a gossip without our address and public key
     pub fn new(
@@ -150,13 +256,66 @@ impl Gossip {
         id: &ed25519::SecretKey,
         subscriptions: SubscriptionsSlice<'_>,
     ) -> Self {
-        let mut info = GossipInfo(0);
+        Self::new_at(address, id, subscriptions, Time::now())
+    }
+
+    /// same as [`Gossip::new`] but with the given time instead of the
+    /// current time
+    ///
+    /// useful to produce reproducible gossips (tests, simulations with a
+    /// virtual clock...)
+    pub fn new_at(
+        address: SocketAddr,
+        id: &ed25519::SecretKey,
+        subscriptions: SubscriptionsSlice<'_>,
+        time: Time,
+    ) -> Self {
+        Self::build(address, id, subscriptions, None, time)
+    }
+
+    /// same as [`Gossip::new`] but with an application defined metadata
+    ///
+    /// The metadata are signed with the rest of the gossip and cannot be
+    /// more than [`Gossip::MAX_METADATA_SIZE`] bytes. Note that peers
+    /// running a version without metadata support reject such gossips.
+    pub fn new_with_metadata(
+        address: SocketAddr,
+        id: &ed25519::SecretKey,
+        subscriptions: SubscriptionsSlice<'_>,
+        metadata: &[u8],
+    ) -> Result<Self, GossipError> {
+        if metadata.len() > Self::MAX_METADATA_SIZE {
+            return Err(GossipError::MetadataTooLarge {
+                size: metadata.len(),
+            });
+        }
+
+        Ok(Self::build(
+            address,
+            id,
+            subscriptions,
+            Some(metadata),
+            Time::now(),
+        ))
+    }
+
+    fn build(
+        address: SocketAddr,
+        id: &ed25519::SecretKey,
+        subscriptions: SubscriptionsSlice<'_>,
+        metadata: Option<&[u8]>,
+        time: Time,
+    ) -> Self {
+        let mut info = GossipInfo(0, 0);
         info.set_num_subscriptions(subscriptions.number_subscriptions());
         if address.is_ipv4() {
             info.set_ipv4()
         } else if address.is_ipv6() {
             info.set_ipv6()
         }
+        if let Some(metadata) = metadata {
+            info.set_metadata(metadata.len());
+        }
 
         let signature_start = info.signature_start();
         let signature_end = info.signature_end();
@@ -165,7 +324,7 @@ impl Gossip {
 
         bytes[INFO_INDEX..INFO_END].copy_from_slice(&info.0.to_be_bytes());
         bytes[ID_INDEX..ID_END].copy_from_slice(id.public_key().as_ref());
-        bytes[TIME_INDEX..TIME_END].copy_from_slice(&Time::now().to_be_bytes());
+        bytes[TIME_INDEX..TIME_END].copy_from_slice(&time.to_be_bytes());
 
         match address.ip() {
             IpAddr::V4(v4) => {
@@ -180,6 +339,11 @@ impl Gossip {
         bytes[info.port_start()..info.port_end()].copy_from_slice(&address.port().to_be_bytes());
         bytes[info.subscription_start()..info.subscription_end()]
             .copy_from_slice(subscriptions.as_ref());
+        if let Some(metadata) = metadata {
+            bytes[info.metadata_start()..info.metadata_content_start()]
+                .copy_from_slice(&(metadata.len() as u16).to_be_bytes());
+            bytes[info.metadata_content_start()..info.metadata_end()].copy_from_slice(metadata);
+        }
 
         let signature = id.sign(&bytes[..signature_start]);
         bytes[signature_start..signature_end].copy_from_slice(signature.as_ref());
@@ -187,32 +351,108 @@ impl Gossip {
         Self(bytes)
     }
 
+    /// parse and verify the gossip, taking ownership of the bytes
+    ///
+    /// see [`GossipSlice::try_from_slice`] for the verification performed
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, GossipError> {
+        let _ = GossipSlice::try_from_slice(&bytes)?;
+        Ok(Self(bytes))
+    }
+
+    /// rebuild the gossip with the same address and subscriptions but with
+    /// a fresh timestamp, signed with the given key
+    ///
+    /// This is only possible for our own gossip: the secret key needs to
+    /// match the gossip's public key.
+    pub fn resign(&self, id: &ed25519::SecretKey) -> Result<Self, GossipError> {
+        if id.public_key() != self.id() {
+            return Err(GossipError::InvalidSecretKey);
+        }
+
+        Ok(Self::build(
+            self.address(),
+            id,
+            self.subscriptions(),
+            self.metadata(),
+            Time::now(),
+        ))
+    }
+
+    /// rebuild our gossip with its subscriptions in canonical form (see
+    /// [`GossipSlice::is_canonical`]), signed with the given key
+    ///
+    /// Like [`Gossip::resign`], the secret key needs to match the gossip's
+    /// public key.
+    pub fn canonicalize(&self, id: &ed25519::SecretKey) -> Result<Self, GossipError> {
+        if id.public_key() != self.id() {
+            return Err(GossipError::InvalidSecretKey);
+        }
+
+        let mut subscriptions = self.subscriptions_owned();
+        subscriptions.canonicalize();
+
+        Ok(Self::build(
+            self.address(),
+            id,
+            subscriptions.as_slice(),
+            self.metadata(),
+            Time::now(),
+        ))
+    }
+
     pub fn as_slice(&self) -> GossipSlice<'_> {
         GossipSlice(&self.0)
     }
 
+    pub fn is_canonical(&self) -> bool {
+        self.as_slice().is_canonical()
+    }
+
     pub fn id(&self) -> ed25519::PublicKey {
         self.as_slice().id()
     }
 
+    pub fn id_matches(&self, key: &ed25519::PublicKey) -> bool {
+        self.as_slice().id_matches(key)
+    }
+
     pub fn time(&self) -> Time {
         self.as_slice().time()
     }
 
+    pub fn content_id(&self) -> [u8; 32] {
+        self.as_slice().content_id()
+    }
+
     pub fn address(&self) -> SocketAddr {
         self.as_slice().address()
     }
 
+    pub fn metadata(&self) -> Option<&[u8]> {
+        self.as_slice().metadata()
+    }
+
     pub fn subscriptions(&self) -> SubscriptionsSlice<'_> {
         self.as_slice().subscriptions()
     }
 
+    /// owned copy of the subscriptions, allocated to the exact number
+    /// of subscriptions of the gossip
+    pub fn subscriptions_owned(&self) -> Subscriptions {
+        self.subscriptions().to_owned()
+    }
+
     pub fn signature(&self) -> ed25519::Signature {
         self.as_slice().signature()
     }
 }
 
 impl<'a> GossipSlice<'a> {
+    /// parse and verify the gossip
+    ///
+    /// on top of checking the gossip is well formed, this function verifies
+    /// the signature: the content of the gossip is bound to the public key
+    /// it contains (see [`GossipSlice::id_matches`]).
     pub fn try_from_slice(slice: &'a [u8]) -> Result<Self, GossipError> {
         let info = GossipInfo::try_from_slice(slice)?;
 
@@ -242,13 +482,22 @@ impl<'a> GossipSlice<'a> {
         }
     }
 
+    /// create a gossip slice without verifying the subscriptions and the
+    /// signature
+    ///
+    /// # Panics
+    ///
+    /// the size of the header is checked against the size of the slice
+    /// so the accessors never read out of bounds: this function panics if
+    /// they do not match. Use [`GossipSlice::try_from_slice`] for the
+    /// slices that have not been verified already.
     pub fn from_slice_unchecked(slice: &'a [u8]) -> Self {
-        #[cfg(debug_assertions)]
-        {
-            let info =
-                GossipInfo::try_from_slice(slice).expect("should have the gossip info slice");
-            debug_assert_eq!(info.signature_end(), slice.len());
-        }
+        let info = GossipInfo::try_from_slice(slice).expect("should have the gossip info slice");
+        assert_eq!(
+            info.signature_end(),
+            slice.len(),
+            "the gossip's header does not match the size of the slice"
+        );
 
         Self(slice)
     }
@@ -258,12 +507,7 @@ impl<'a> GossipSlice<'a> {
     }
 
     fn info(&self) -> GossipInfo {
-        let sub = u16::from_be_bytes(
-            self.0[INFO_INDEX..INFO_END]
-                .try_into()
-                .expect("valid 2 bytes on the slice"),
-        );
-        GossipInfo(sub)
+        GossipInfo::try_from_slice(self.0).expect("valid gossip info")
     }
 
     pub fn id(&self) -> ed25519::PublicKey {
@@ -273,6 +517,60 @@ impl<'a> GossipSlice<'a> {
         ed25519::PublicKey::from(pk)
     }
 
+    /// check the gossip's public key is the given key
+    ///
+    /// Use it to make sure a gossip received from an authenticated
+    /// connection does belong to the peer that authenticated the session.
+    /// Because [`GossipSlice::try_from_slice`] already verified the
+    /// signature, a match means the gossip has been issued by that peer.
+    pub fn id_matches(&self, key: &ed25519::PublicKey) -> bool {
+        &self.id() == key
+    }
+
+    /// check the subscriptions are sorted by topic and without duplicated
+    /// topics
+    ///
+    /// A gossip that is not canonical is still valid (older peers do not
+    /// sort their subscriptions) but it can be normalized by its issuer
+    /// with [`Gossip::canonicalize`].
+    pub fn is_canonical(&self) -> bool {
+        self.subscriptions().is_canonical()
+    }
+
+    /// hash of the content of the gossip: the id, the address, the
+    /// subscriptions (regardless of their order) and the metadata
+    ///
+    /// The time and the signature are not part of the content: the same
+    /// announcement issued at different times has the same content id.
+    pub fn content_id(&self) -> [u8; 32] {
+        let info = self.info();
+        let mut subscriptions: Vec<&[u8]> = self.0
+            [info.subscription_start()..info.subscription_end()]
+            .chunks(Subscription::SIZE)
+            .collect();
+        subscriptions.sort_unstable();
+
+        let mut id = [0; 32];
+        let mut hasher = Blake2b::new(id.len());
+        hasher.input(&self.0[ID_INDEX..ID_END]);
+        hasher.input(&[info.is_ipv4() as u8]);
+        hasher.input(&self.0[info.ip_start()..info.port_end()]);
+        for subscription in subscriptions {
+            hasher.input(subscription);
+        }
+        // the presence and the length of the metadata are hashed too so
+        // they cannot be mistaken for more subscriptions
+        if let Some(metadata) = self.metadata() {
+            hasher.input(&[1]);
+            hasher.input(&(metadata.len() as u16).to_be_bytes());
+            hasher.input(metadata);
+        } else {
+            hasher.input(&[0]);
+        }
+        hasher.result(&mut id);
+        id
+    }
+
     pub fn time(&self) -> Time {
         let time = u32::from_be_bytes(
             self.0[TIME_INDEX..TIME_END]
@@ -331,6 +629,17 @@ impl<'a> GossipSlice<'a> {
         SubscriptionsSlice::from_slice_unchecked(slice)
     }
 
+    /// the application defined metadata, if any (see
+    /// [`Gossip::new_with_metadata`])
+    pub fn metadata(&self) -> Option<&'a [u8]> {
+        let info = self.info();
+        if info.has_metadata() {
+            Some(&self.0[info.metadata_content_start()..info.metadata_end()])
+        } else {
+            None
+        }
+    }
+
     fn signed_data(&self) -> &[u8] {
         let info = self.info();
         &self.0[..info.signature_start()]
@@ -383,6 +692,7 @@ impl fmt::Debug for Gossip {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{InterestLevel, Topic};
     use keynesis::Seed;
     use quickcheck::{Arbitrary, Gen};
 
@@ -440,6 +750,184 @@ mod tests {
         assert_eq!(decoded.address(), address);
     }
 
+    #[test]
+    fn resign() {
+        let mut rng = Seed::from([0; Seed::SIZE]).into_rand_chacha();
+        let id = ed25519::SecretKey::new(&mut rng);
+        let other = ed25519::SecretKey::new(&mut rng);
+
+        let address: SocketAddr = "127.0.0.1:9876".parse().unwrap();
+        let subscriptions = Subscriptions::new();
+
+        let gossip = Gossip::new_at(address, &id, subscriptions.as_slice(), Time::from(0));
+        let resigned = gossip.resign(&id).unwrap();
+
+        GossipSlice::try_from_slice(resigned.as_ref()).unwrap();
+        assert!(resigned.time() > gossip.time());
+        assert_eq!(resigned.address(), gossip.address());
+
+        assert!(matches!(
+            gossip.resign(&other),
+            Err(GossipError::InvalidSecretKey)
+        ));
+    }
+
+    #[test]
+    fn id_matches() {
+        let mut rng = Seed::from([0; Seed::SIZE]).into_rand_chacha();
+        let id = ed25519::SecretKey::new(&mut rng);
+        let other = ed25519::SecretKey::new(&mut rng);
+
+        let address: SocketAddr = "127.0.0.1:9876".parse().unwrap();
+        let gossip = Gossip::new(address, &id, Subscriptions::new().as_slice());
+
+        assert!(gossip.id_matches(&id.public_key()));
+        assert!(!gossip.id_matches(&other.public_key()));
+    }
+
+    #[test]
+    fn subscriptions_owned() {
+        let mut rng = Seed::from([0; Seed::SIZE]).into_rand_chacha();
+        let id = ed25519::SecretKey::new(&mut rng);
+        let mut g = Gen::new(1024);
+
+        let mut subscriptions = Subscriptions::new();
+        for _ in 0..3 {
+            subscriptions
+                .push(Subscription::arbitrary(&mut g).as_slice())
+                .unwrap();
+        }
+
+        let address: SocketAddr = "127.0.0.1:9876".parse().unwrap();
+        let gossip = Gossip::new(address, &id, subscriptions.as_slice());
+        let owned = gossip.subscriptions_owned();
+
+        assert_eq!(owned.as_slice().as_ref(), gossip.subscriptions().as_ref());
+        assert_eq!(owned.capacity(), 3);
+    }
+
+    #[test]
+    fn size_for() {
+        let mut rng = Seed::from([0; Seed::SIZE]).into_rand_chacha();
+        let id = ed25519::SecretKey::new(&mut rng);
+        let mut g = Gen::new(1024);
+
+        let mut subscriptions = Subscriptions::new();
+        for _ in 0..3 {
+            subscriptions
+                .push(Subscription::arbitrary(&mut g).as_slice())
+                .unwrap();
+        }
+
+        for address in &["127.0.0.1:9876", "[::1]:9876"] {
+            let address: SocketAddr = address.parse().unwrap();
+            let gossip = Gossip::new(address, &id, subscriptions.as_slice());
+            assert_eq!(Gossip::size_for(address, 3), gossip.as_ref().len());
+        }
+    }
+
+    #[test]
+    fn canonical() {
+        let mut rng = Seed::from([0; Seed::SIZE]).into_rand_chacha();
+        let id = ed25519::SecretKey::new(&mut rng);
+        let other = ed25519::SecretKey::new(&mut rng);
+        let address: SocketAddr = "127.0.0.1:9876".parse().unwrap();
+
+        let topic1 = Topic::new([1; Topic::SIZE]);
+        let topic2 = Topic::new([2; Topic::SIZE]);
+
+        let mut sorted = Subscriptions::new();
+        sorted
+            .push(Subscription::new(topic1, InterestLevel::new(1)).as_slice())
+            .unwrap();
+        sorted
+            .push(Subscription::new(topic2, InterestLevel::new(2)).as_slice())
+            .unwrap();
+        let gossip = Gossip::new(address, &id, sorted.as_slice());
+        assert!(gossip.is_canonical());
+
+        let mut unsorted = Subscriptions::new();
+        unsorted
+            .push(Subscription::new(topic2, InterestLevel::new(2)).as_slice())
+            .unwrap();
+        unsorted
+            .push(Subscription::new(topic1, InterestLevel::new(1)).as_slice())
+            .unwrap();
+        unsorted
+            .push(Subscription::new(topic1, InterestLevel::new(0)).as_slice())
+            .unwrap();
+        let gossip = Gossip::new(address, &id, unsorted.as_slice());
+        assert!(!gossip.is_canonical());
+        assert!(GossipSlice::try_from_slice(gossip.as_ref()).is_ok());
+
+        assert!(matches!(
+            gossip.canonicalize(&other),
+            Err(GossipError::InvalidSecretKey)
+        ));
+
+        let canonical = gossip.canonicalize(&id).unwrap();
+        assert!(canonical.is_canonical());
+        assert!(GossipSlice::try_from_slice(canonical.as_ref()).is_ok());
+        assert_eq!(canonical.subscriptions_owned(), sorted);
+    }
+
+    fn claim_max_subscriptions(gossip: &Gossip) -> Vec<u8> {
+        let mut bytes = gossip.as_ref().to_vec();
+        let mut info = GossipInfo::try_from_slice(&bytes).unwrap();
+        info.set_num_subscriptions(Subscriptions::MAX_NUM_SUBSCRIPTIONS);
+        bytes[INFO_INDEX..INFO_END].copy_from_slice(&info.0.to_be_bytes());
+        bytes
+    }
+
+    #[test]
+    fn header_claims_too_many_subscriptions() {
+        let address: SocketAddr = "127.0.0.1:9876".parse().unwrap();
+        let gossip = Gossip::new(address, &golden_key(), golden_subscriptions(1).as_slice());
+        let bytes = claim_max_subscriptions(&gossip);
+
+        assert!(matches!(
+            GossipSlice::try_from_slice(&bytes),
+            Err(GossipError::InvalidSize { .. })
+        ));
+        assert!(Gossip::from_bytes(bytes).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "the gossip's header does not match the size of the slice")]
+    fn unchecked_header_claims_too_many_subscriptions() {
+        let address: SocketAddr = "127.0.0.1:9876".parse().unwrap();
+        let gossip = Gossip::new(address, &golden_key(), golden_subscriptions(1).as_slice());
+        let bytes = claim_max_subscriptions(&gossip);
+
+        let _ = GossipSlice::from_slice_unchecked(&bytes);
+    }
+
+    #[test]
+    fn invalid_size_message() {
+        let mut rng = Seed::from([0; Seed::SIZE]).into_rand_chacha();
+        let id = ed25519::SecretKey::new(&mut rng);
+
+        let address: SocketAddr = "127.0.0.1:9876".parse().unwrap();
+        let gossip = Gossip::new(address, &id, Subscriptions::new().as_slice());
+        let bytes = gossip.as_ref();
+        let expected = bytes.len();
+
+        let error = GossipSlice::try_from_slice(&bytes[..expected - 1]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!("Invalid gossip size, expected exactly {}", expected)
+        );
+
+        let error = GossipSlice::try_from_slice(&bytes[..1]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Invalid gossip size, expected at least {}",
+                GossipInfo::SIZE
+            )
+        );
+    }
+
     #[quickcheck]
     fn parse_valid_gossip(gossip: Gossip) -> bool {
         let slice = gossip.as_slice();
@@ -450,4 +938,241 @@ mod tests {
         assert_eq!(gossip.0, decoded.0);
         true
     }
+
+    #[test]
+    fn metadata() {
+        let address: SocketAddr = "127.0.0.1:9876".parse().unwrap();
+        let subscriptions = golden_subscriptions(3);
+
+        let gossip = Gossip::new(address, &golden_key(), subscriptions.as_slice());
+        assert_eq!(gossip.metadata(), None);
+
+        let gossip =
+            Gossip::new_with_metadata(address, &golden_key(), subscriptions.as_slice(), b"app")
+                .unwrap();
+        let decoded = GossipSlice::try_from_slice(gossip.as_ref()).unwrap();
+        assert_eq!(decoded.metadata(), Some(&b"app"[..]));
+        assert_eq!(decoded.subscriptions().to_owned(), subscriptions);
+        assert_eq!(decoded.address(), address);
+
+        let resigned = gossip.resign(&golden_key()).unwrap();
+        assert_eq!(resigned.metadata(), Some(&b"app"[..]));
+
+        let empty =
+            Gossip::new_with_metadata(address, &golden_key(), subscriptions.as_slice(), &[])
+                .unwrap();
+        assert_eq!(empty.metadata(), Some(&[][..]));
+        assert_ne!(empty.content_id(), gossip.content_id());
+    }
+
+    #[test]
+    fn metadata_content_id() {
+        let address: SocketAddr = "127.0.0.1:9876".parse().unwrap();
+        let subscriptions = golden_subscriptions(3);
+
+        let none = Gossip::new(address, &golden_key(), subscriptions.as_slice());
+        let empty =
+            Gossip::new_with_metadata(address, &golden_key(), subscriptions.as_slice(), &[])
+                .unwrap();
+        assert_ne!(none.content_id(), empty.content_id());
+
+        // metadata with the bytes of the subscription that sorts last
+        let more = golden_subscriptions(4);
+        let last = more.as_slice().iter().last().unwrap();
+        let metadata = Gossip::new_with_metadata(
+            address,
+            &golden_key(),
+            subscriptions.as_slice(),
+            last.as_ref(),
+        )
+        .unwrap();
+        let more = Gossip::new(address, &golden_key(), more.as_slice());
+        assert_ne!(metadata.content_id(), more.content_id());
+    }
+
+    #[test]
+    fn max_size() {
+        let address: SocketAddr = "[::1]:9876".parse().unwrap();
+        let mut subscriptions = Subscriptions::new();
+        for i in 0..Gossip::MAX_NUM_SUBSCRIPTIONS {
+            let mut topic = [0; Topic::SIZE];
+            topic[..8].copy_from_slice(&(i as u64).to_be_bytes());
+            let subscription = Subscription::new(Topic::new(topic), InterestLevel::new(1));
+            subscriptions.push(subscription.as_slice()).unwrap();
+        }
+        let metadata = vec![0; Gossip::MAX_METADATA_SIZE];
+
+        let gossip =
+            Gossip::new_with_metadata(address, &golden_key(), subscriptions.as_slice(), &metadata)
+                .unwrap();
+        assert_eq!(gossip.as_ref().len(), Gossip::MAX_SIZE);
+    }
+
+    #[test]
+    fn metadata_too_large() {
+        let address: SocketAddr = "127.0.0.1:9876".parse().unwrap();
+        let subscriptions = golden_subscriptions(0);
+        let metadata = vec![0; Gossip::MAX_METADATA_SIZE + 1];
+
+        assert!(matches!(
+            Gossip::new_with_metadata(address, &golden_key(), subscriptions.as_slice(), &metadata),
+            Err(GossipError::MetadataTooLarge { size }) if size == metadata.len()
+        ));
+
+        let gossip = Gossip::new_with_metadata(
+            address,
+            &golden_key(),
+            subscriptions.as_slice(),
+            &metadata[..Gossip::MAX_METADATA_SIZE],
+        )
+        .unwrap();
+        let info = GossipInfo::try_from_slice(gossip.as_ref()).unwrap();
+        let mut bytes = gossip.as_ref().to_vec();
+        bytes[info.metadata_start()..info.metadata_content_start()]
+            .copy_from_slice(&(metadata.len() as u16).to_be_bytes());
+        assert!(matches!(
+            GossipSlice::try_from_slice(&bytes),
+            Err(GossipError::MetadataTooLarge { .. })
+        ));
+
+        let error = GossipSlice::try_from_slice(&bytes[..info.metadata_start()]).unwrap_err();
+        assert!(matches!(error, GossipError::InvalidSize { .. }));
+    }
+
+    #[test]
+    fn new_at_is_reproducible() {
+        let address: SocketAddr = "127.0.0.1:9876".parse().unwrap();
+        let time = Time::from(42);
+        let subscriptions = golden_subscriptions(2);
+
+        let gossip = Gossip::new_at(address, &golden_key(), subscriptions.as_slice(), time);
+        let again = Gossip::new_at(address, &golden_key(), subscriptions.as_slice(), time);
+
+        assert_eq!(gossip, again);
+        assert_eq!(gossip.time(), time);
+    }
+
+    #[test]
+    fn content_id() {
+        let address: SocketAddr = "127.0.0.1:9876".parse().unwrap();
+        let id = golden_key();
+
+        let gossip = Gossip::new_at(
+            address,
+            &id,
+            golden_subscriptions(2).as_slice(),
+            Time::from(1),
+        );
+        let later = Gossip::new_at(
+            address,
+            &id,
+            golden_subscriptions(2).as_slice(),
+            Time::from(2),
+        );
+        assert_ne!(gossip, later);
+        assert_eq!(gossip.content_id(), later.content_id());
+
+        let mut reversed = Subscriptions::new();
+        for subscription in golden_subscriptions(2).iter().rev() {
+            reversed.push(subscription).unwrap();
+        }
+        let reordered = Gossip::new_at(address, &id, reversed.as_slice(), Time::from(1));
+        assert_eq!(gossip.content_id(), reordered.content_id());
+
+        let other = Gossip::new_at(
+            address,
+            &id,
+            golden_subscriptions(3).as_slice(),
+            Time::from(1),
+        );
+        assert_ne!(gossip.content_id(), other.content_id());
+
+        let moved: SocketAddr = "127.0.0.1:9877".parse().unwrap();
+        let moved = Gossip::new_at(
+            moved,
+            &id,
+            golden_subscriptions(2).as_slice(),
+            Time::from(1),
+        );
+        assert_ne!(gossip.content_id(), moved.content_id());
+    }
+
+    // golden vectors: these tests fail if the wire format of the gossips
+    // changes (header bits, order of the fields...)
+
+    fn golden_key() -> ed25519::SecretKey {
+        ed25519::SecretKey::new(Seed::from([7; Seed::SIZE]).into_rand_chacha())
+    }
+
+    /// `n` subscriptions to the topics `[1; 32]`, `[2; 32]`... with the
+    /// interest levels `1`, `2`...
+    fn golden_subscriptions(n: u8) -> Subscriptions {
+        let mut subscriptions = Subscriptions::new();
+        for i in 1..=n {
+            let subscription =
+                Subscription::new(Topic::new([i; Topic::SIZE]), InterestLevel::new(i));
+            subscriptions.push(subscription.as_slice()).unwrap();
+        }
+        subscriptions
+    }
+
+    fn assert_golden(address: &str, subscriptions: Subscriptions, expected: &str) {
+        let address: SocketAddr = address.parse().unwrap();
+        let time = Time::from(0x0102_0304);
+        let gossip = Gossip::new_at(address, &golden_key(), subscriptions.as_slice(), time);
+
+        assert_eq!(hex::encode(gossip.as_ref()), expected);
+
+        let decoded = Gossip::from_bytes(hex::decode(expected).unwrap()).unwrap();
+        assert_eq!(decoded.address(), address);
+        assert_eq!(decoded.time(), time);
+        assert_eq!(decoded.subscriptions_owned(), subscriptions);
+    }
+
+    #[test]
+    fn gossip_golden_ipv4() {
+        let expected = concat!(
+            "8001",
+            "e79a4e621583674785585866dc854fb85e2b5d208693483a4cdecd901f43d85d",
+            "01020304",
+            "c0a80001",
+            "2694",
+            "010101010101010101010101010101010101010101010101010101010101010101",
+            "f8097edd857cf7889bdea02393a37755c1920d0e40ee96c17c0dfaa587656927",
+            "a05356a05be8be10365da1a284b0ad79bbde4ec0965239c87ff51802fd0e9b0e",
+        );
+        assert_golden("192.168.0.1:9876", golden_subscriptions(1), expected);
+    }
+
+    #[test]
+    fn gossip_golden_ipv6() {
+        let expected = concat!(
+            "0001",
+            "e79a4e621583674785585866dc854fb85e2b5d208693483a4cdecd901f43d85d",
+            "01020304",
+            "20010db8000000000000000000000001",
+            "1092",
+            "010101010101010101010101010101010101010101010101010101010101010101",
+            "51e2676f4df26647ea068958cb833f9629c69f52dd1de0ce540563750e209e0a",
+            "3b38616895d7e0bc20253b12a3b6028f78c67cadf5725fdefeee5841d2da1401",
+        );
+        assert_golden("[2001:db8::1]:4242", golden_subscriptions(1), expected);
+    }
+
+    #[test]
+    fn gossip_golden_subscriptions() {
+        let expected = concat!(
+            "8003",
+            "e79a4e621583674785585866dc854fb85e2b5d208693483a4cdecd901f43d85d",
+            "01020304",
+            "0a000002",
+            "04d2",
+            "010101010101010101010101010101010101010101010101010101010101010101",
+            "020202020202020202020202020202020202020202020202020202020202020202",
+            "030303030303030303030303030303030303030303030303030303030303030303",
+            "be3165bd16eec0ded14e3ada78c654935f9298dcd2f918a8f13be2afd6340002",
+            "0fde0a7492724c4b12e2a2fc9f5e7174c50654cb43731cd283cdbcd85824db09",
+        );
+        assert_golden("10.0.0.2:1234", golden_subscriptions(3), expected);
+    }
 }
@@ -1,5 +1,5 @@
 use crate::{
-    layer::{Layer, ViewBuilder},
+    layer::{Layer, LayerState, ViewBuilder},
     InterestLevel, PriorityMap, Profile, Topic,
 };
 use keynesis::key::ed25519;
@@ -22,7 +22,11 @@ impl Layer for Cyclon {
     }
 
     fn view(&mut self, builder: &mut ViewBuilder) {
-        self.nodes.iter().for_each(|(k, _)| builder.add(k));
+        for (k, _) in self.nodes.iter() {
+            if !builder.add(k) {
+                break;
+            }
+        }
     }
 
     fn remove(&mut self, id: &ed25519::PublicKey) {
@@ -41,4 +45,28 @@ impl Layer for Cyclon {
     fn unsubscribe(&mut self, _topic: &Topic) {}
 
     fn subscriptions(&self, _output: &mut PriorityMap<InterestLevel, Topic>) {}
+
+    fn is_stable(&self) -> bool {
+        true
+    }
+
+    fn snapshot(&self) -> LayerState {
+        LayerState::Cyclon(self.nodes.iter().map(|(k, _)| *k).collect())
+    }
+
+    fn fork(&self) -> Option<Box<dyn Layer>> {
+        let mut cyclon = Self::new(self.nodes.cap());
+        cyclon.restore(self.snapshot());
+        Some(Box::new(cyclon))
+    }
+
+    fn restore(&mut self, state: LayerState) {
+        if let LayerState::Cyclon(nodes) = state {
+            self.nodes.clear();
+            // the least recently used first
+            for node in nodes.into_iter().rev() {
+                self.nodes.put(node, ());
+            }
+        }
+    }
 }
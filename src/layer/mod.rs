@@ -1,11 +1,12 @@
 mod cyclon;
+mod rendezvous;
 mod rings;
 mod vicinity;
 
-pub use self::{cyclon::Cyclon, rings::Rings, vicinity::Vicinity};
-use crate::{InterestLevel, PriorityMap, Profile, Topic};
+pub use self::{cyclon::Cyclon, rendezvous::Rendezvous, rings::Rings, vicinity::Vicinity};
+use crate::{profile::Proximity, InterestLevel, PriorityMap, Profile, Topic};
 use keynesis::key::ed25519;
-use std::collections::HashSet;
+use std::collections::{hash_map, HashMap};
 
 pub trait Layer: Send {
     fn name(&self) -> &'static str;
@@ -20,6 +21,85 @@ pub trait Layer: Send {
     fn subscriptions(&self, output: &mut PriorityMap<InterestLevel, Topic>);
 
     fn populate(&mut self, our_profile: &Profile, new_profile: &Profile);
+
+    /// check if the view for [`Selection::Topic`] only depends on the nodes
+    /// subscribed to that topic
+    ///
+    /// this allows the `Topology` to keep the cached contributions of the
+    /// layer (see [`Layer::is_stable`]) to the views of the topics not
+    /// affected by a change. The default implementation returns `false`.
+    fn is_topic_scoped(&self) -> bool {
+        false
+    }
+
+    /// check if [`Layer::view`] selects the same nodes on every call, as
+    /// long as the layer is not modified (populated, subscribed...)
+    ///
+    /// this allows the `Topology` to cache the contribution of the layer
+    /// to the views. Layers rotating their selection must return `false`,
+    /// which is the default implementation.
+    fn is_stable(&self) -> bool {
+        false
+    }
+
+    /// capture the internal state of the layer
+    ///
+    /// the default implementation returns [`LayerState::Empty`]
+    fn snapshot(&self) -> LayerState {
+        LayerState::Empty
+    }
+
+    /// replace the internal state of the layer with a state previously
+    /// returned by [`Layer::snapshot`]
+    ///
+    /// the state of a different kind of layer is ignored
+    fn restore(&mut self, _state: LayerState) {}
+
+    /// create an independent copy of the layer, with the same state
+    ///
+    /// the default implementation returns `None`: the layer cannot be
+    /// forked (see [`Topology::fork`](crate::Topology::fork)).
+    fn fork(&self) -> Option<Box<dyn Layer>> {
+        None
+    }
+}
+
+/// the internal state of a layer, see [`Layer::snapshot`]
+///
+/// the entries are listed in the order the layer would select them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LayerState {
+    /// the layer has no state to save
+    Empty,
+    /// for every subscribed topic, the predecessors and the successors
+    Rings(Vec<(Topic, Vec<ed25519::PublicKey>, Vec<ed25519::PublicKey>)>),
+    /// the nodes and their proximity
+    Vicinity(Vec<(Proximity, ed25519::PublicKey)>),
+    /// the nodes
+    Cyclon(Vec<ed25519::PublicKey>),
+    /// for every subscribed topic, the ranked nodes (selected or not)
+    Rendezvous(Vec<(Topic, Vec<ed25519::PublicKey>)>),
+}
+
+impl LayerState {
+    /// all the nodes referenced in the state
+    pub fn nodes(&self) -> Vec<ed25519::PublicKey> {
+        match self {
+            Self::Empty => Vec::new(),
+            Self::Rings(links) => links
+                .iter()
+                .flat_map(|(_, predecessors, successors)| predecessors.iter().chain(successors))
+                .copied()
+                .collect(),
+            Self::Vicinity(nodes) => nodes.iter().map(|(_, node)| *node).collect(),
+            Self::Cyclon(nodes) => nodes.clone(),
+            Self::Rendezvous(links) => links
+                .iter()
+                .flat_map(|(_, nodes)| nodes.iter())
+                .copied()
+                .collect(),
+        }
+    }
 }
 
 pub trait LayerBuilder {
@@ -27,7 +107,7 @@ pub trait LayerBuilder {
     fn build_for_gossip(&self) -> Vec<Box<dyn Layer>>;
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Selection {
     Topic { topic: Topic },
     Any,
@@ -39,7 +119,12 @@ pub struct ViewBuilder {
 
     selection: Selection,
 
-    view: HashSet<ed25519::PublicKey>,
+    max: Option<usize>,
+
+    /// index of the node in `view`
+    indices: HashMap<ed25519::PublicKey, usize>,
+    /// the nodes in the order they have been added to the view
+    view: Vec<(ed25519::PublicKey, Selection)>,
 }
 
 impl ViewBuilder {
@@ -47,7 +132,22 @@ impl ViewBuilder {
         Self {
             event_origin: None,
             selection,
-            view: HashSet::new(),
+            max: None,
+            indices: HashMap::new(),
+            view: Vec::new(),
+        }
+    }
+
+    /// create a `ViewBuilder` that will not hold more than `max` nodes
+    ///
+    /// once the limit is reached, [`ViewBuilder::add`] becomes a no-op
+    pub fn with_capacity(selection: Selection, max: usize) -> Self {
+        Self {
+            event_origin: None,
+            selection,
+            max: Some(max),
+            indices: HashMap::with_capacity(max),
+            view: Vec::with_capacity(max),
         }
     }
 
@@ -64,11 +164,135 @@ impl ViewBuilder {
         self.selection
     }
 
-    pub fn add(&mut self, node: &ed25519::PublicKey) {
-        self.view.insert(*node);
+    /// add the node to the view
+    ///
+    /// returns `false` if the node could not be added because the view is
+    /// already full. Layers can use it to stop looking for more nodes.
+    pub fn add(&mut self, node: &ed25519::PublicKey) -> bool {
+        self.add_via(node, Selection::Any)
+    }
+
+    /// add the node to the view, recording the selection that made
+    /// the layer pick this node (for example the topic of a ring).
+    ///
+    /// If the node was already added via [`Selection::Any`], the more
+    /// specific selection is kept.
+    pub fn add_via(&mut self, node: &ed25519::PublicKey, via: Selection) -> bool {
+        let full = self.is_full();
+        match self.indices.entry(*node) {
+            hash_map::Entry::Occupied(occupied) => {
+                let entry = &mut self.view[*occupied.get()];
+                if let Selection::Any = entry.1 {
+                    entry.1 = via;
+                }
+                true
+            }
+            hash_map::Entry::Vacant(_) if full => false,
+            hash_map::Entry::Vacant(vacant) => {
+                vacant.insert(self.view.len());
+                self.view.push((*node, via));
+                true
+            }
+        }
     }
 
-    pub(crate) fn build(self) -> HashSet<ed25519::PublicKey> {
+    /// check if the view has reached its maximum number of nodes
+    pub fn is_full(&self) -> bool {
+        matches!(self.max, Some(max) if self.view.len() >= max)
+    }
+
+    /// the nodes in the order the layers added them
+    pub(crate) fn build_ordered(self) -> Vec<ed25519::PublicKey> {
+        self.view.into_iter().map(|(node, _)| node).collect()
+    }
+
+    pub(crate) fn build_detailed(self) -> Vec<(ed25519::PublicKey, Selection)> {
         self.view
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Gossip, Subscription, Subscriptions};
+    use keynesis::Seed;
+    use std::net::SocketAddr;
+
+    #[test]
+    fn view_builder_capacity() {
+        let address: SocketAddr = "127.0.0.1:9876".parse().unwrap();
+        let mut rng = Seed::from([0; Seed::SIZE]).into_rand_chacha();
+        let ours = Profile::new(address, &ed25519::SecretKey::new(&mut rng));
+
+        let mut layer = Cyclon::new(10);
+        for _ in 0..10 {
+            let profile = Profile::new(address, &ed25519::SecretKey::new(&mut rng));
+            layer.populate(&ours, &profile);
+        }
+
+        let mut builder = ViewBuilder::with_capacity(Selection::Any, 3);
+        layer.view(&mut builder);
+
+        assert!(builder.is_full());
+        assert_eq!(builder.build_ordered().len(), 3);
+    }
+
+    fn assert_snapshot_roundtrip<L, F>(new: F)
+    where
+        L: Layer,
+        F: Fn() -> L,
+    {
+        let address: SocketAddr = "127.0.0.1:9876".parse().unwrap();
+        let mut rng = Seed::from([1; Seed::SIZE]).into_rand_chacha();
+        let topics = [Topic::new([1; Topic::SIZE]), Topic::new([2; Topic::SIZE])];
+
+        let profile_with = |id: &ed25519::SecretKey, topics: &[Topic]| {
+            let mut subscriptions = Subscriptions::new();
+            for topic in topics {
+                let subscription = Subscription::new(*topic, InterestLevel::new(1));
+                subscriptions.push(subscription.as_slice()).unwrap();
+            }
+            Profile::from_gossip(Gossip::new(address, id, subscriptions.as_slice()))
+        };
+
+        let ours = profile_with(&ed25519::SecretKey::new(&mut rng), &topics[..1]);
+        let mut layer = new();
+        for topic in topics.iter() {
+            layer.subscribe(*topic);
+        }
+        for i in 0..12 {
+            let id = ed25519::SecretKey::new(&mut rng);
+            layer.populate(&ours, &profile_with(&id, &topics[i % 2..]));
+        }
+
+        let mut restored = new();
+        restored.restore(layer.snapshot());
+        assert_eq!(restored.snapshot(), layer.snapshot());
+
+        let selections = [
+            Selection::Any,
+            Selection::Topic { topic: topics[0] },
+            Selection::Topic { topic: topics[1] },
+        ];
+        for selection in selections.iter() {
+            let mut expected = ViewBuilder::new(*selection);
+            layer.view(&mut expected);
+            let mut builder = ViewBuilder::new(*selection);
+            restored.view(&mut builder);
+
+            let expected = expected.build_detailed();
+            if let Selection::Any = selection {
+                assert!(!expected.is_empty());
+            }
+            assert_eq!(builder.build_detailed(), expected);
+        }
+    }
+
+    #[test]
+    fn snapshot_roundtrip() {
+        assert_snapshot_roundtrip(|| Rings::new(4));
+        assert_snapshot_roundtrip(|| Vicinity::new(5));
+        assert_snapshot_roundtrip(|| Cyclon::new(5));
+        assert_snapshot_roundtrip(|| Rendezvous::new(3));
+    }
+}
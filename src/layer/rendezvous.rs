@@ -0,0 +1,239 @@
+use crate::{
+    layer::{Layer, LayerState, Selection, ViewBuilder},
+    InterestLevel, PriorityMap, Profile, Subscriptions, Topic,
+};
+use keynesis::{
+    hash::{Blake2b, Digest as _},
+    key::ed25519,
+};
+
+type Score = [u8; 32];
+
+/// rendezvous hashing layer
+///
+/// For every subscribed topic, the known peers subscribed to the same topic
+/// are ranked by `hash(topic || peer_id)` and the `length` best ranked peers
+/// are selected. Unlike the [`Rings`](crate::layer::Rings) the selection does
+/// not depend on our own id: the same topic maps to the same peers for every
+/// node as long as the membership does not change.
+///
+/// More peers than `length` are ranked for every topic so the selection can
+/// be backfilled with the next best ranked peers when a selected peer is
+/// removed.
+pub struct Rendezvous {
+    length: usize,
+    links: lru::LruCache<Topic, PriorityMap<Score, ed25519::PublicKey>>,
+}
+
+impl Rendezvous {
+    /// number of ranked peers kept per topic for every selected peer
+    const CANDIDATES_FACTOR: usize = 4;
+
+    pub fn new(length: usize) -> Self {
+        Self {
+            length,
+            links: lru::LruCache::new(Subscriptions::MAX_NUM_SUBSCRIPTIONS),
+        }
+    }
+
+    fn score(topic: &Topic, id: &ed25519::PublicKey) -> Score {
+        let mut score = [0; 32];
+        let mut hasher = Blake2b::new(score.len());
+        hasher.input(topic.as_ref());
+        hasher.input(id.as_ref());
+        hasher.result(&mut score);
+        score
+    }
+
+    fn candidates(&self) -> PriorityMap<Score, ed25519::PublicKey> {
+        PriorityMap::new(self.length.saturating_mul(Self::CANDIDATES_FACTOR))
+    }
+}
+
+impl Layer for Rendezvous {
+    fn name(&self) -> &'static str {
+        "poldercast::rendezvous"
+    }
+
+    fn view(&mut self, builder: &mut ViewBuilder) {
+        match builder.selection() {
+            Selection::Any => {
+                for (topic, nodes) in self.links.iter() {
+                    let via = Selection::Topic { topic: *topic };
+                    for (_, node) in nodes.iter().take(self.length) {
+                        if !builder.add_via(node, via) {
+                            return;
+                        }
+                    }
+                }
+            }
+            selection @ Selection::Topic { topic } => {
+                if let Some(nodes) = self.links.peek(&topic) {
+                    for (_, node) in nodes.iter().take(self.length) {
+                        if !builder.add_via(node, selection) {
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn remove(&mut self, id: &ed25519::PublicKey) {
+        for (_, nodes) in self.links.iter_mut() {
+            nodes.remove(id);
+        }
+    }
+    fn reset(&mut self) {
+        self.links.clear();
+    }
+
+    fn populate(&mut self, our_profile: &Profile, new_profile: &Profile) {
+        let id = new_profile.id();
+        if id == our_profile.id() {
+            return;
+        }
+
+        for topic in new_profile.gossip().subscriptions().iter_topics() {
+            if let Some(nodes) = self.links.peek_mut(&topic) {
+                nodes.put(Self::score(&topic, &id), id);
+            }
+        }
+    }
+
+    fn is_topic_scoped(&self) -> bool {
+        true
+    }
+
+    fn subscribe(&mut self, topic: Topic) {
+        if !self.links.contains(&topic) {
+            let candidates = self.candidates();
+            self.links.put(topic, candidates);
+        }
+    }
+    fn unsubscribe(&mut self, topic: &Topic) {
+        self.links.pop(topic);
+    }
+    fn subscriptions(&self, _output: &mut PriorityMap<InterestLevel, Topic>) {}
+
+    fn is_stable(&self) -> bool {
+        true
+    }
+
+    fn snapshot(&self) -> LayerState {
+        LayerState::Rendezvous(
+            self.links
+                .iter()
+                .map(|(topic, nodes)| (*topic, nodes.iter().map(|(_, node)| *node).collect()))
+                .collect(),
+        )
+    }
+
+    fn fork(&self) -> Option<Box<dyn Layer>> {
+        let mut rendezvous = Self::new(self.length);
+        rendezvous.restore(self.snapshot());
+        Some(Box::new(rendezvous))
+    }
+
+    fn restore(&mut self, state: LayerState) {
+        if let LayerState::Rendezvous(links) = state {
+            self.links.clear();
+            for (topic, nodes) in links.into_iter().rev() {
+                let mut map = self.candidates();
+                for node in nodes.into_iter().rev() {
+                    map.put(Self::score(&topic, &node), node);
+                }
+                self.links.put(topic, map);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Gossip, Subscription};
+    use keynesis::Seed;
+    use std::net::SocketAddr;
+
+    fn profile(seed: u8, topic: Topic) -> Profile {
+        let rng = Seed::from([seed; Seed::SIZE]).into_rand_chacha();
+        let address: SocketAddr = "127.0.0.1:9876".parse().unwrap();
+        let mut subscriptions = Subscriptions::new();
+        subscriptions
+            .push(Subscription::new(topic, InterestLevel::new(1)).as_slice())
+            .unwrap();
+        let id = ed25519::SecretKey::new(rng);
+        Profile::from_gossip(Gossip::new(address, &id, subscriptions.as_slice()))
+    }
+
+    fn top(layer: &mut Rendezvous, topic: Topic) -> Vec<ed25519::PublicKey> {
+        let mut builder = ViewBuilder::new(Selection::Topic { topic });
+        layer.view(&mut builder);
+        builder.build_ordered()
+    }
+
+    #[test]
+    fn stable_selection() {
+        let topic = Topic::new([1; Topic::SIZE]);
+        let ours = profile(0, topic);
+        let peers: Vec<Profile> = (1..6).map(|seed| profile(seed, topic)).collect();
+
+        let mut ranked: Vec<ed25519::PublicKey> = peers.iter().map(|p| p.id()).collect();
+        ranked.sort_by_key(|id| std::cmp::Reverse(Rendezvous::score(&topic, id)));
+
+        let mut layer = Rendezvous::new(1);
+        layer.subscribe(topic);
+        for peer in peers.iter() {
+            layer.populate(&ours, peer);
+        }
+
+        assert_eq!(top(&mut layer, topic), vec![ranked[0]]);
+        assert_eq!(top(&mut layer, topic), vec![ranked[0]]);
+
+        // the selection does not depend on the order peers are learnt
+        let mut other = Rendezvous::new(2);
+        other.subscribe(topic);
+        for peer in peers.iter().rev() {
+            other.populate(&ours, peer);
+        }
+        let mut selected = top(&mut other, topic);
+        selected.sort_by_key(|id| std::cmp::Reverse(Rendezvous::score(&topic, id)));
+        assert_eq!(selected, vec![ranked[0], ranked[1]]);
+
+        // removing the best peer promotes the next best ranked one
+        other.remove(&ranked[0]);
+        assert_eq!(top(&mut other, topic), vec![ranked[1], ranked[2]]);
+    }
+
+    #[test]
+    fn backfill_on_remove() {
+        let topic = Topic::new([1; Topic::SIZE]);
+        let ours = profile(0, topic);
+        let peers: Vec<Profile> = (1..6).map(|seed| profile(seed, topic)).collect();
+
+        let mut ranked: Vec<ed25519::PublicKey> = peers.iter().map(|p| p.id()).collect();
+        ranked.sort_by_key(|id| std::cmp::Reverse(Rendezvous::score(&topic, id)));
+
+        let mut layer = Rendezvous::new(1);
+        layer.subscribe(topic);
+        // our own profile is never selected
+        layer.populate(&ours, &ours);
+        assert!(top(&mut layer, topic).is_empty());
+
+        for peer in peers.iter() {
+            layer.populate(&ours, peer);
+        }
+        assert_eq!(top(&mut layer, topic), vec![ranked[0]]);
+
+        layer.remove(&ranked[0]);
+        assert_eq!(top(&mut layer, topic), vec![ranked[1]]);
+        layer.remove(&ranked[1]);
+        assert_eq!(top(&mut layer, topic), vec![ranked[2]]);
+
+        // stop adding nodes once the view is full
+        let mut builder = ViewBuilder::with_capacity(Selection::Any, 0);
+        layer.view(&mut builder);
+        assert!(builder.build_ordered().is_empty());
+    }
+}
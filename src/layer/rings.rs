@@ -1,5 +1,5 @@
 use crate::{
-    layer::{Layer, Selection, ViewBuilder},
+    layer::{Layer, LayerState, Selection, ViewBuilder},
     InterestLevel, PriorityMap, Profile, Subscription, Subscriptions, Topic,
 };
 use keynesis::key::ed25519;
@@ -49,7 +49,9 @@ impl Ring {
         InterestLevel::new(level)
     }
 
-    pub fn recipients(&mut self, builder: &mut ViewBuilder) {
+    pub fn recipients(&mut self, topic: Topic, builder: &mut ViewBuilder) {
+        let via = Selection::Topic { topic };
+
         let (predecessor, successor) = if let Some(from) = builder.origin() {
             (
                 !self.predecessors.contains(from),
@@ -61,19 +63,44 @@ impl Ring {
 
         if predecessor {
             if let Some((key, ())) = self.predecessors.pop_lru() {
-                builder.add(&key);
+                builder.add_via(&key, via);
                 self.predecessors.put(key, ());
             }
         }
 
         if successor {
             if let Some((key, ())) = self.successors.pop_lru() {
-                builder.add(&key);
+                builder.add_via(&key, via);
                 self.successors.put(key, ());
             }
         }
     }
 
+    fn snapshot(&self) -> (Vec<ed25519::PublicKey>, Vec<ed25519::PublicKey>) {
+        (
+            self.predecessors.iter().map(|(k, _)| *k).collect(),
+            self.successors.iter().map(|(k, _)| *k).collect(),
+        )
+    }
+
+    fn restore(
+        length: u8,
+        predecessors: Vec<ed25519::PublicKey>,
+        successors: Vec<ed25519::PublicKey>,
+    ) -> Self {
+        let mut ring = Self::new(length);
+        // the least recently used first
+        for key in predecessors.into_iter().rev() {
+            ring.predecessors.put(key, ());
+        }
+        for key in successors.into_iter().rev() {
+            ring.successors.put(key, ());
+        }
+        ring.current_low = ring.predecessors.iter().map(|(k, _)| k).min().copied();
+        ring.current_max = ring.successors.iter().map(|(k, _)| k).max().copied();
+        ring
+    }
+
     pub fn receive_gossips(&mut self, our_id: &ed25519::PublicKey, their_id: &ed25519::PublicKey) {
         match our_id.cmp(their_id) {
             Ordering::Equal => {
@@ -129,7 +156,7 @@ impl Rings {
     }
 
     pub fn subscriptions(&self) -> Subscriptions {
-        let mut subscriptions = Subscriptions::new();
+        let mut subscriptions = Subscriptions::with_capacity(self.links.len());
 
         for (topic, ring) in self.links.iter() {
             let interest_level = ring.interest_level();
@@ -148,18 +175,21 @@ impl Rings {
             }
         }
 
+        // the LRU order of the rings is not meaningful
+        subscriptions.canonicalize();
+
         subscriptions
     }
 
     fn recipients_for_event(&mut self, topic: &Topic, builder: &mut ViewBuilder) {
         if let Some(ring) = self.links.get_mut(topic) {
-            ring.recipients(builder);
+            ring.recipients(*topic, builder);
         }
     }
 
     fn recipients_for_all(&mut self, builder: &mut ViewBuilder) {
-        for (_, ring) in self.links.iter_mut() {
-            ring.recipients(builder);
+        for (topic, ring) in self.links.iter_mut() {
+            ring.recipients(*topic, builder);
         }
     }
 
@@ -206,10 +236,19 @@ impl Layer for Rings {
         self.receive_gossip(
             &our_profile.id(),
             &new_profile.id(),
-            new_profile.subscriptions().iter().map(|sub| sub.topic()),
+            new_profile.subscriptions().iter_topics(),
         )
     }
 
+    fn is_topic_scoped(&self) -> bool {
+        true
+    }
+
+    fn is_stable(&self) -> bool {
+        // the neighbors are rotated on every view
+        false
+    }
+
     fn subscribe(&mut self, topic: Topic) {
         if !self.links.contains(&topic) {
             self.links.put(topic, Ring::new(self.length));
@@ -231,4 +270,32 @@ impl Layer for Rings {
             output.put(interest_level, *topic);
         }
     }
+
+    fn snapshot(&self) -> LayerState {
+        LayerState::Rings(
+            self.links
+                .iter()
+                .map(|(topic, ring)| {
+                    let (predecessors, successors) = ring.snapshot();
+                    (*topic, predecessors, successors)
+                })
+                .collect(),
+        )
+    }
+
+    fn fork(&self) -> Option<Box<dyn Layer>> {
+        let mut rings = Self::new(self.length);
+        rings.restore(self.snapshot());
+        Some(Box::new(rings))
+    }
+
+    fn restore(&mut self, state: LayerState) {
+        if let LayerState::Rings(links) = state {
+            self.links.clear();
+            for (topic, predecessors, successors) in links.into_iter().rev() {
+                let ring = Ring::restore(self.length, predecessors, successors);
+                self.links.put(topic, ring);
+            }
+        }
+    }
 }
@@ -1,5 +1,5 @@
 use crate::{
-    layer::{Layer, ViewBuilder},
+    layer::{Layer, LayerState, ViewBuilder},
     profile::Proximity,
     InterestLevel, PriorityMap, Profile, Topic,
 };
@@ -23,7 +23,11 @@ impl Layer for Vicinity {
     }
 
     fn view(&mut self, builder: &mut ViewBuilder) {
-        self.nodes.iter().for_each(|(_, v)| builder.add(v));
+        for (_, v) in self.nodes.iter() {
+            if !builder.add(v) {
+                break;
+            }
+        }
     }
 
     fn remove(&mut self, id: &ed25519::PublicKey) {
@@ -43,4 +47,30 @@ impl Layer for Vicinity {
     fn unsubscribe(&mut self, _: &Topic) {}
 
     fn subscriptions(&self, _output: &mut PriorityMap<InterestLevel, Topic>) {}
+
+    fn is_stable(&self) -> bool {
+        true
+    }
+
+    fn snapshot(&self) -> LayerState {
+        LayerState::Vicinity(self.nodes.iter().map(|(p, k)| (*p, *k)).collect())
+    }
+
+    fn fork(&self) -> Option<Box<dyn Layer>> {
+        Some(Box::new(Self {
+            nodes: self.nodes.clone(),
+        }))
+    }
+
+    fn restore(&mut self, state: LayerState) {
+        if let LayerState::Vicinity(nodes) = state {
+            self.nodes.clear();
+            // the snapshot is in the order of `iter`: re-put the entries from
+            // the lowest proximity (and the least recently used within a
+            // proximity) up so `iter` yields them in the same order
+            for (proximity, node) in nodes.into_iter().rev() {
+                self.nodes.put(proximity, node);
+            }
+        }
+    }
 }
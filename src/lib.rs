@@ -13,11 +13,14 @@ mod topology;
 pub use self::{
     gossip::{Gossip, GossipError, GossipSlice},
     priority_map::PriorityMap,
-    profile::Profile,
-    profiles::Profiles,
+    profile::{Profile, ProfileDiff, Proximity},
+    profiles::{PoolKind, Profiles, PutOutcome},
     topic::{
         InterestLevel, Subscription, SubscriptionError, SubscriptionIter, SubscriptionSlice,
         Subscriptions, SubscriptionsSlice, Topic,
     },
-    topology::Topology,
+    topology::{
+        AddressPreference, ConsistencyError, ImportOutcome, OverlapReport, RingNeighbors, Topology,
+        ViewEntry, ViewOrdering,
+    },
 };
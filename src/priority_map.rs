@@ -1,7 +1,8 @@
 use std::{
     borrow::Borrow,
-    collections::{btree_map, hash_map::RandomState, BTreeMap, HashMap},
+    collections::{btree_map, hash_map::RandomState, BTreeMap, HashMap, HashSet},
     hash::{BuildHasher, Hash},
+    ops::Bound,
     ptr::NonNull,
     rc::Rc,
 };
@@ -37,6 +38,57 @@ where
     pub fn new(cap: usize) -> Self {
         PriorityMap::new_with_map(cap, HashMap::with_capacity(cap))
     }
+
+    /// build the map from entries sorted by descending priority, as
+    /// returned by [`PriorityMap::iter`]
+    ///
+    /// If a value is present more than once, the first occurrence is kept.
+    /// Then only the first `cap` entries are kept. The entries of a same
+    /// priority keep their relative order. The indices are built directly
+    /// in one pass (with one lookup per priority rather than per entry)
+    /// instead of calling [`PriorityMap::put`] for every entry.
+    pub fn from_sorted<I>(cap: usize, entries: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let mut map = Self::new(cap);
+        let mut seen = HashSet::with_capacity(cap);
+        let entries: Vec<(K, V)> = entries
+            .into_iter()
+            .filter(|(_, value)| seen.insert(value.clone()))
+            .take(cap)
+            .collect();
+
+        // the lowest priorities and least recently used entries first: the
+        // entries of a same priority are consecutive if the input is sorted
+        let mut group: Option<(Rc<K>, PriorityGroup<K, V>)> = None;
+        for (key, value) in entries.into_iter().rev() {
+            let mut entry = Box::new(Entry::new(key, value));
+            let entry_ptr: NonNull<Entry<K, V>> = unsafe { NonNull::new_unchecked(entry.as_mut()) };
+            let k = entry.key.clone();
+            let v = entry.value.clone();
+            map.by_value.insert(v.clone(), entry);
+
+            match group.as_mut() {
+                Some((priority, entries)) if *priority == k => {
+                    entries.put(v, entry_ptr);
+                }
+                _ => {
+                    if let Some((priority, entries)) = group.take() {
+                        map.insert_group(priority, entries);
+                    }
+                    let mut entries = lru::LruCache::unbounded();
+                    entries.put(v, entry_ptr);
+                    group = Some((k, entries));
+                }
+            }
+        }
+        if let Some((priority, entries)) = group {
+            map.insert_group(priority, entries);
+        }
+
+        map
+    }
 }
 
 impl<K, V, H> PriorityMap<K, V, H>
@@ -45,6 +97,18 @@ where
     V: Eq + Clone + Hash,
     H: BuildHasher,
 {
+    /// create a map holding at most `cap` entries with room for `capacity`
+    /// entries before it needs to grow
+    pub fn with_capacity(cap: usize, capacity: usize) -> Self
+    where
+        H: Default,
+    {
+        Self::new_with_map(
+            cap,
+            HashMap::with_capacity_and_hasher(capacity, H::default()),
+        )
+    }
+
     pub fn new_with(cap: usize, hash_builder: H) -> Self {
         Self::new_with_map(cap, HashMap::with_capacity_and_hasher(cap, hash_builder))
     }
@@ -115,6 +179,19 @@ where
             })
     }
 
+    /// same as [`PriorityMap::iter`] but only the entries with a priority
+    /// strictly greater than `min`
+    pub fn iter_above(&self, min: &K) -> impl Iterator<Item = (&'_ K, &'_ V)> {
+        self.by_priority
+            .range::<K, _>((Bound::Excluded(min), Bound::Unbounded))
+            .rev()
+            .flat_map(|(_, v)| v.iter())
+            .map(|(_, v)| {
+                let p = unsafe { v.as_ref() };
+                (p.key.borrow(), p.value.borrow())
+            })
+    }
+
     pub fn get<Q>(&self, k: &Q) -> Option<(&'_ K, &'_ V)>
     where
         Rc<V>: Borrow<Q>,
@@ -143,6 +220,50 @@ where
             }
         }
 
+        self.insert(key, value);
+    }
+
+    /// insert the entry only if the value is not already present or if the
+    /// new priority is strictly greater than the current one
+    ///
+    /// returns `true` if the entry has been inserted or updated
+    pub fn put_if_higher(&mut self, key: K, value: V) -> bool {
+        match self.get(&value) {
+            Some((current, _)) if current >= &key => false,
+            Some(_) => {
+                // the entry is already accounted for in the cap
+                self.insert(key, value);
+                true
+            }
+            None => {
+                self.put(key, value.clone());
+                self.contains(&value)
+            }
+        }
+    }
+
+    /// get the entry associated to the given value, inserting it with the
+    /// priority returned by `priority` if it is not already present
+    ///
+    /// If the map is full, the entries with the lowest priority are evicted
+    /// to make room for the new entry.
+    pub fn get_or_insert_with<F>(&mut self, value: V, priority: F) -> (&'_ K, &'_ V)
+    where
+        F: FnOnce() -> K,
+    {
+        if !self.contains(&value) {
+            while self.len() >= self.cap {
+                self.pop_lowest();
+            }
+
+            self.insert(priority(), value.clone());
+        }
+
+        self.get(&value)
+            .expect("the entry is present or has just been inserted")
+    }
+
+    fn insert(&mut self, key: K, value: V) {
         self.remove(&value);
 
         let entry = Entry::new(key, value);
@@ -161,6 +282,24 @@ where
             .put(v, entry_ptr);
     }
 
+    /// add a group of entries, already in `by_value`, to `by_priority`
+    ///
+    /// the group is merged with the existing group of the same priority
+    /// (if the entries given to [`PriorityMap::from_sorted`] were not
+    /// sorted)
+    fn insert_group(&mut self, priority: Rc<K>, mut entries: PriorityGroup<K, V>) {
+        match self.by_priority.entry(priority) {
+            btree_map::Entry::Vacant(vacant) => {
+                vacant.insert(entries);
+            }
+            btree_map::Entry::Occupied(mut occupied) => {
+                while let Some((v, entry_ptr)) = entries.pop_lru() {
+                    occupied.get_mut().put(v, entry_ptr);
+                }
+            }
+        }
+    }
+
     pub fn resize(&mut self, cap: usize) {
         // return early if capacity doesn't change
         if cap == self.cap {
@@ -209,6 +348,25 @@ where
     }
 }
 
+impl<K, V, H> Clone for PriorityMap<K, V, H>
+where
+    K: Ord + Clone,
+    V: Eq + Clone + Hash,
+    H: BuildHasher + Clone,
+{
+    fn clone(&self) -> Self {
+        let mut map = Self::new_with(self.cap, self.by_value.hasher().clone());
+
+        // the lowest priorities and least recently used entries first
+        let entries: Vec<(&K, &V)> = self.iter().collect();
+        for (key, value) in entries.into_iter().rev() {
+            map.insert(key.clone(), value.clone());
+        }
+
+        map
+    }
+}
+
 unsafe impl<K: Send, V: Send> Send for PriorityMap<K, V> {}
 unsafe impl<K: Sync, V: Sync> Sync for PriorityMap<K, V> {}
 
@@ -226,6 +384,84 @@ mod tests {
         assert_eq!(map.len(), 1);
     }
 
+    #[test]
+    fn from_sorted() {
+        let entries = vec![
+            (3, "a".to_owned()),
+            (2, "b".to_owned()),
+            (2, "c".to_owned()),
+            (1, "d".to_owned()),
+            (0, "e".to_owned()),
+        ];
+
+        let mut map = PriorityMap::from_sorted(4, entries.clone());
+        let collected: Vec<(u32, String)> = map.iter().map(|(k, v)| (*k, v.clone())).collect();
+        assert_eq!(collected, entries[..4].to_vec());
+
+        let (k, v) = map.pop_lowest().unwrap();
+        assert_eq!((*k, v.as_str()), (1, "d"));
+        assert!(map.remove(&"b".to_owned()));
+        assert_eq!(map.len(), 2);
+
+        map.put(5, "f".to_owned());
+        map.put(4, "g".to_owned());
+        map.put(0, "h".to_owned());
+        let values: Vec<&str> = map.iter().map(|(_, v)| v.as_str()).collect();
+        assert_eq!(values, vec!["f", "g", "a", "c"]);
+
+        let map = PriorityMap::from_sorted(10, vec![(2, "a"), (1, "a")]);
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.get(&"a"), Some((&2, &"a")));
+
+        // the duplicates do not count toward the cap
+        let map =
+            PriorityMap::from_sorted(3, vec![(4, "a"), (3, "a"), (2, "b"), (2, "a"), (1, "c")]);
+        assert_eq!(map.len(), 3);
+        let collected: Vec<(u32, &str)> = map.iter().map(|(k, v)| (*k, *v)).collect();
+        assert_eq!(collected, vec![(4, "a"), (2, "b"), (1, "c")]);
+
+        // the indices stay consistent if the entries are not sorted
+        let mut map = PriorityMap::from_sorted(10, vec![(1, "a"), (2, "b"), (1, "c")]);
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.iter().count(), 3);
+        assert_eq!(map.iter().next(), Some((&2, &"b")));
+        assert!(map.remove(&"a"));
+        assert!(map.remove(&"c"));
+        assert_eq!(map.pop_lowest().map(|(k, v)| (*k, *v)), Some((2, "b")));
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn clone() {
+        let mut map = PriorityMap::new(3);
+        map.put(1, "a".to_owned());
+        map.put(2, "b".to_owned());
+        map.put(1, "c".to_owned());
+
+        let mut cloned = map.clone();
+        let entries = |map: &PriorityMap<u32, String>| -> Vec<(u32, String)> {
+            map.iter().map(|(k, v)| (*k, v.clone())).collect()
+        };
+        assert_eq!(entries(&cloned), entries(&map));
+
+        cloned.put(3, "d".to_owned());
+        assert_eq!(cloned.len(), 3);
+        assert!(!cloned.contains(&"a".to_owned()));
+        assert!(map.contains(&"a".to_owned()));
+        assert!(!map.contains(&"d".to_owned()));
+    }
+
+    #[test]
+    fn with_capacity() {
+        let mut map = PriorityMap::<u32, u32>::with_capacity(100, 2);
+        assert!(map.capacity() < 100);
+
+        for i in 0..150 {
+            map.put(i, i);
+        }
+        assert_eq!(map.len(), 100);
+    }
+
     #[test]
     fn contains() {
         let mut map = PriorityMap::<u32, String>::new(10);
@@ -274,6 +510,51 @@ mod tests {
         assert_eq!(iter.next(), None);
     }
 
+    #[test]
+    fn get_or_insert_with() {
+        let mut map = PriorityMap::<u32, String>::new(2);
+        map.put(2, "2".to_owned());
+        map.put(3, "3".to_owned());
+
+        let (k, v) = map.get_or_insert_with("2".to_owned(), || unreachable!());
+        assert_eq!((k, v), (&2, &"2".to_owned()));
+        assert_eq!(map.len(), 2);
+
+        let (k, v) = map.get_or_insert_with("1".to_owned(), || 1);
+        assert_eq!((k, v), (&1, &"1".to_owned()));
+        assert_eq!(map.len(), 2);
+        assert!(!map.contains(&"2".to_owned()));
+        assert!(map.contains(&"3".to_owned()));
+    }
+
+    #[test]
+    fn put_if_higher() {
+        let mut map = PriorityMap::<u32, String>::new(2);
+
+        assert!(map.put_if_higher(2, "entry".to_owned()));
+        assert_eq!(
+            map.get(&"entry".to_owned()),
+            Some((&2, &"entry".to_owned()))
+        );
+
+        assert!(map.put_if_higher(3, "entry".to_owned()));
+        assert_eq!(
+            map.get(&"entry".to_owned()),
+            Some((&3, &"entry".to_owned()))
+        );
+
+        assert!(!map.put_if_higher(1, "entry".to_owned()));
+        assert!(!map.put_if_higher(3, "entry".to_owned()));
+        assert_eq!(
+            map.get(&"entry".to_owned()),
+            Some((&3, &"entry".to_owned()))
+        );
+
+        map.put(2, "other".to_owned());
+        assert!(!map.put_if_higher(1, "lowest".to_owned()));
+        assert_eq!(map.len(), 2);
+    }
+
     #[test]
     fn ordering() {
         let mut map = PriorityMap::<u32, String>::new(10);
@@ -296,4 +577,26 @@ mod tests {
         assert_eq!(iter.next(), Some((&1u32, &"1".to_owned())));
         assert_eq!(iter.next(), None);
     }
+
+    #[test]
+    fn iter_above() {
+        let mut map = PriorityMap::<u32, String>::new(10);
+        map.put(3, "3".to_owned());
+        map.put(1, "1".to_owned());
+        map.put(2, "2".to_owned());
+        map.put(5, "5".to_owned());
+        map.put(5, "five".to_owned());
+        map.put(6, "6".to_owned());
+        map.put(4, "4".to_owned());
+
+        let mut iter = map.iter_above(&3);
+
+        assert_eq!(iter.next(), Some((&6u32, &"6".to_owned())));
+        assert_eq!(iter.next(), Some((&5u32, &"five".to_owned())));
+        assert_eq!(iter.next(), Some((&5u32, &"5".to_owned())));
+        assert_eq!(iter.next(), Some((&4u32, &"4".to_owned())));
+        assert_eq!(iter.next(), None);
+
+        assert_eq!(map.iter_above(&6).next(), None);
+    }
 }
@@ -3,8 +3,13 @@ use crate::{
     Gossip, PriorityMap, Subscription,
 };
 use keynesis::{key::ed25519, passport::block::Time};
-use std::net::SocketAddr;
+use std::{
+    collections::BTreeSet,
+    hash::{Hash, Hasher},
+    net::SocketAddr,
+};
 
+#[derive(Clone)]
 pub struct Profile {
     subscriptions: PriorityMap<InterestLevel, Topic>,
     gossip: Gossip,
@@ -16,9 +21,30 @@ pub struct Proximity {
     proximity: usize,
 }
 
+/// what changed between a profile and a newer gossip of the same peer,
+/// see [`Profile::diff`]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ProfileDiff {
+    pub address_changed: bool,
+    /// the topics of the newer gossip the profile was not subscribed to,
+    /// sorted
+    pub topics_added: Vec<Topic>,
+    /// the topics of the profile the newer gossip is not subscribed to,
+    /// sorted
+    pub topics_removed: Vec<Topic>,
+}
+
+impl ProfileDiff {
+    /// check nothing relevant has changed (the interest levels are
+    /// not considered)
+    pub fn is_empty(&self) -> bool {
+        !self.address_changed && self.topics_added.is_empty() && self.topics_removed.is_empty()
+    }
+}
+
 impl Profile {
     pub fn new(address: SocketAddr, id: &ed25519::SecretKey) -> Self {
-        let gossip = Gossip::new(address, id, Subscriptions::new().as_slice());
+        let gossip = Gossip::new(address, id, Subscriptions::with_capacity(0).as_slice());
 
         Self {
             gossip,
@@ -27,7 +53,11 @@ impl Profile {
     }
 
     pub fn from_gossip(gossip: Gossip) -> Self {
-        let mut subscriptions = PriorityMap::new(Subscriptions::MAX_NUM_SUBSCRIPTIONS);
+        // only reserve what the gossip needs, the map grows with the
+        // future subscriptions
+        let reserved = gossip.subscriptions().number_subscriptions().max(1);
+        let mut subscriptions =
+            PriorityMap::with_capacity(Subscriptions::MAX_NUM_SUBSCRIPTIONS, reserved);
 
         for subscription in gossip.subscriptions() {
             let interest_level = subscription.interest_level();
@@ -57,10 +87,26 @@ impl Profile {
         &self.gossip
     }
 
+    /// the bytes of the profile's gossip
+    ///
+    /// use [`Gossip::from_bytes`] and [`Profile::from_gossip`] to
+    /// rebuild the profile
+    pub fn to_gossip_bytes(&self) -> &[u8] {
+        self.gossip.as_ref()
+    }
+
     pub(crate) fn commit_gossip(&mut self, id: &ed25519::SecretKey) -> &Gossip {
+        self.commit_gossip_with_address(self.address(), id)
+    }
+
+    pub(crate) fn commit_gossip_with_address(
+        &mut self,
+        address: SocketAddr,
+        id: &ed25519::SecretKey,
+    ) -> &Gossip {
         let subscriptions = self.subscriptions();
 
-        self.gossip = Gossip::new(self.address(), id, subscriptions.as_slice());
+        self.gossip = Gossip::new(address, id, subscriptions.as_slice());
 
         &self.gossip
     }
@@ -77,10 +123,25 @@ impl Profile {
         self.gossip.address()
     }
 
+    /// the application defined metadata of the last received gossip
+    pub fn metadata(&self) -> Option<&[u8]> {
+        self.gossip.metadata()
+    }
+
+    /// the subscriptions of the profile, sorted by topic
+    ///
+    /// the order does not depend on the interest levels so updating an
+    /// interest level does not reorder the subscriptions of the gossip
     pub fn subscriptions(&self) -> Subscriptions {
-        let mut subscriptions = Subscriptions::new();
-        for (interest_level, topic) in self.subscriptions.iter() {
-            let sub = Subscription::new(*topic, *interest_level);
+        let mut sorted: Vec<Subscription> = self
+            .subscriptions
+            .iter()
+            .map(|(interest_level, topic)| Subscription::new(*topic, *interest_level))
+            .collect();
+        sorted.sort_unstable();
+
+        let mut subscriptions = Subscriptions::with_capacity(sorted.len());
+        for sub in sorted {
             subscriptions
                 .push(sub.as_slice())
                 .expect("We are already limiting the number of internal subscriptions");
@@ -88,6 +149,30 @@ impl Profile {
         subscriptions
     }
 
+    /// the interest level of the profile for the given topic
+    ///
+    /// returns `None` if the profile is not subscribed to the topic
+    pub fn interest_in(&self, topic: &Topic) -> Option<InterestLevel> {
+        self.subscriptions
+            .get(topic)
+            .map(|(interest_level, _)| *interest_level)
+    }
+
+    /// compare the profile with a newer gossip of the same peer
+    ///
+    /// useful to decide how to react to the update: reconnect if the
+    /// address has changed, re-evaluate the rings if the topics have...
+    pub fn diff(&self, newer: &Gossip) -> ProfileDiff {
+        let current: BTreeSet<Topic> = self.subscriptions.iter().map(|(_, topic)| *topic).collect();
+        let newer_topics: BTreeSet<Topic> = newer.subscriptions().iter_topics().collect();
+
+        ProfileDiff {
+            address_changed: self.address() != newer.address(),
+            topics_added: newer_topics.difference(&current).copied().collect(),
+            topics_removed: current.difference(&newer_topics).copied().collect(),
+        }
+    }
+
     pub fn proximity_to(&self, to: &Self) -> Proximity {
         let mut priority_score = 0;
         let mut proximity_score = 0;
@@ -127,8 +212,253 @@ impl Ord for Proximity {
     }
 }
 
+/// profiles are compared by identity: two profiles with the same id are
+/// equal, regardless of their subscriptions or of when they were issued
+impl PartialEq for Profile {
+    fn eq(&self, other: &Self) -> bool {
+        self.id() == other.id()
+    }
+}
+
+impl Eq for Profile {}
+
+impl Hash for Profile {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id().hash(state)
+    }
+}
+
 impl From<Gossip> for Profile {
     fn from(gossip: Gossip) -> Self {
         Self::from_gossip(gossip)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use keynesis::Seed;
+
+    #[test]
+    fn gossip_bytes_roundtrip() {
+        let mut rng = Seed::from([0; Seed::SIZE]).into_rand_chacha();
+        let id = ed25519::SecretKey::new(&mut rng);
+        let address: SocketAddr = "127.0.0.1:9876".parse().unwrap();
+
+        let mut subscriptions = Subscriptions::new();
+        for byte in 1..4 {
+            let topic = Topic::new([byte; Topic::SIZE]);
+            let subscription = Subscription::new(topic, InterestLevel::new(byte));
+            subscriptions.push(subscription.as_slice()).unwrap();
+        }
+        let profile = Profile::from_gossip(Gossip::new(address, &id, subscriptions.as_slice()));
+
+        let bytes = profile.to_gossip_bytes().to_vec();
+        let decoded = Profile::from_gossip(Gossip::from_bytes(bytes).unwrap());
+
+        assert_eq!(decoded.id(), profile.id());
+        assert_eq!(decoded.address(), address);
+        assert_eq!(decoded.subscriptions(), subscriptions);
+    }
+
+    #[test]
+    fn interest_in() {
+        let mut rng = Seed::from([0; Seed::SIZE]).into_rand_chacha();
+        let id = ed25519::SecretKey::new(&mut rng);
+        let address: SocketAddr = "127.0.0.1:9876".parse().unwrap();
+
+        let subscribed = Topic::new([1; Topic::SIZE]);
+        let other = Topic::new([2; Topic::SIZE]);
+
+        let mut subscriptions = Subscriptions::new();
+        let subscription = Subscription::new(subscribed, InterestLevel::new(7));
+        subscriptions.push(subscription.as_slice()).unwrap();
+        let profile = Profile::from_gossip(Gossip::new(address, &id, subscriptions.as_slice()));
+
+        assert_eq!(
+            profile.interest_in(&subscribed),
+            Some(InterestLevel::new(7))
+        );
+        assert_eq!(profile.interest_in(&other), None);
+    }
+
+    #[test]
+    fn from_gossip_capacity() {
+        let mut rng = Seed::from([0; Seed::SIZE]).into_rand_chacha();
+        let id = ed25519::SecretKey::new(&mut rng);
+        let address: SocketAddr = "127.0.0.1:9876".parse().unwrap();
+
+        let mut subscriptions = Subscriptions::new();
+        for byte in 1..3 {
+            let topic = Topic::new([byte; Topic::SIZE]);
+            subscriptions
+                .push(Subscription::new(topic, InterestLevel::new(1)).as_slice())
+                .unwrap();
+        }
+
+        let mut profile = Profile::from_gossip(Gossip::new(address, &id, subscriptions.as_slice()));
+        let reserved = profile.subscriptions.capacity();
+        assert!(reserved >= 2);
+        assert!(reserved < Subscriptions::MAX_NUM_SUBSCRIPTIONS);
+
+        // the cap is still the maximum number of subscriptions
+        for index in 0..Subscriptions::MAX_NUM_SUBSCRIPTIONS + 1 {
+            let mut bytes = [0; Topic::SIZE];
+            bytes[..8].copy_from_slice(&(index as u64).to_be_bytes());
+            profile
+                .subscriptions_mut()
+                .put(InterestLevel::new(1), Topic::new(bytes));
+        }
+        assert_eq!(
+            profile.subscriptions.len(),
+            Subscriptions::MAX_NUM_SUBSCRIPTIONS
+        );
+    }
+
+    #[test]
+    fn subscriptions_sorted_by_topic() {
+        let mut rng = Seed::from([0; Seed::SIZE]).into_rand_chacha();
+        let id = ed25519::SecretKey::new(&mut rng);
+        let address: SocketAddr = "127.0.0.1:9876".parse().unwrap();
+
+        let topics: Vec<Topic> = (1..4).map(|byte| Topic::new([byte; Topic::SIZE])).collect();
+
+        let mut profile = Profile::new(address, &id);
+        for (level, topic) in topics.iter().rev().enumerate() {
+            profile
+                .subscriptions_mut()
+                .put(InterestLevel::new(level as u8), *topic);
+        }
+        profile.commit_gossip(&id);
+        assert!(profile.gossip().is_canonical());
+        assert_eq!(
+            profile.subscriptions().iter_topics().collect::<Vec<_>>(),
+            topics
+        );
+
+        // only change the interest levels
+        for (level, topic) in topics.iter().enumerate() {
+            profile
+                .subscriptions_mut()
+                .put(InterestLevel::new(level as u8 + 10), *topic);
+        }
+        profile.commit_gossip(&id);
+        assert!(profile.gossip().is_canonical());
+        assert_eq!(
+            profile.subscriptions().iter_topics().collect::<Vec<_>>(),
+            topics
+        );
+        assert_eq!(
+            profile.interest_in(&topics[2]),
+            Some(InterestLevel::new(12))
+        );
+    }
+
+    #[test]
+    fn subscriptions_equal_interest_deterministic() {
+        let mut rng = Seed::from([0; Seed::SIZE]).into_rand_chacha();
+        let id = ed25519::SecretKey::new(&mut rng);
+        let address: SocketAddr = "127.0.0.1:9876".parse().unwrap();
+
+        let topics: Vec<Topic> = (1..6).map(|byte| Topic::new([byte; Topic::SIZE])).collect();
+
+        // same interest level, inserted and accessed in different orders
+        let mut first = Profile::new(address, &id);
+        for topic in topics.iter() {
+            first.subscriptions_mut().put(InterestLevel::new(1), *topic);
+        }
+        let mut second = Profile::new(address, &id);
+        for topic in topics.iter().rev() {
+            second
+                .subscriptions_mut()
+                .put(InterestLevel::new(1), *topic);
+        }
+        second
+            .subscriptions_mut()
+            .put(InterestLevel::new(1), topics[2]);
+
+        assert_eq!(first.subscriptions(), first.subscriptions());
+        assert_eq!(
+            first.subscriptions().as_slice().as_ref(),
+            second.subscriptions().as_slice().as_ref()
+        );
+        assert_eq!(
+            first.subscriptions().iter_topics().collect::<Vec<_>>(),
+            topics
+        );
+    }
+
+    #[test]
+    fn diff() {
+        let mut rng = Seed::from([0; Seed::SIZE]).into_rand_chacha();
+        let id = ed25519::SecretKey::new(&mut rng);
+
+        let subscriptions_of = |topics: &[u8]| {
+            let mut subscriptions = Subscriptions::new();
+            for byte in topics {
+                let topic = Topic::new([*byte; Topic::SIZE]);
+                let subscription = Subscription::new(topic, InterestLevel::new(1));
+                subscriptions.push(subscription.as_slice()).unwrap();
+            }
+            subscriptions
+        };
+
+        let address: SocketAddr = "127.0.0.1:9876".parse().unwrap();
+        let profile = Profile::from_gossip(Gossip::new(
+            address,
+            &id,
+            subscriptions_of(&[1, 2]).as_slice(),
+        ));
+        assert!(profile.diff(profile.gossip()).is_empty());
+
+        let moved: SocketAddr = "127.0.0.2:9876".parse().unwrap();
+        let newer = Gossip::new(moved, &id, subscriptions_of(&[1, 2, 3]).as_slice());
+
+        let diff = profile.diff(&newer);
+        assert!(diff.address_changed);
+        assert_eq!(diff.topics_added, vec![Topic::new([3; Topic::SIZE])]);
+        assert!(diff.topics_removed.is_empty());
+
+        let newer = Gossip::new(address, &id, subscriptions_of(&[2]).as_slice());
+        let diff = profile.diff(&newer);
+        assert!(!diff.address_changed);
+        assert!(diff.topics_added.is_empty());
+        assert_eq!(diff.topics_removed, vec![Topic::new([1; Topic::SIZE])]);
+    }
+
+    #[test]
+    fn identity() {
+        use std::collections::HashSet;
+
+        let mut rng = Seed::from([0; Seed::SIZE]).into_rand_chacha();
+        let id = ed25519::SecretKey::new(&mut rng);
+        let other = ed25519::SecretKey::new(&mut rng);
+        let address: SocketAddr = "127.0.0.1:9876".parse().unwrap();
+
+        let mut subscriptions = Subscriptions::new();
+        let topic = Topic::new([1; Topic::SIZE]);
+        subscriptions
+            .push(Subscription::new(topic, InterestLevel::new(1)).as_slice())
+            .unwrap();
+
+        let profile = Profile::from_gossip(Gossip::new_at(
+            address,
+            &id,
+            Subscriptions::new().as_slice(),
+            Time::from(1),
+        ));
+        let updated = Profile::from_gossip(Gossip::new_at(
+            address,
+            &id,
+            subscriptions.as_slice(),
+            Time::from(2),
+        ));
+        let stranger = Profile::new(address, &other);
+
+        assert!(profile == updated);
+        assert!(profile != stranger);
+
+        let set: HashSet<Profile> = vec![profile, updated, stranger].into_iter().collect();
+        assert_eq!(set.len(), 2);
+    }
+}
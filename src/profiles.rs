@@ -1,7 +1,31 @@
 use crate::Profile;
 use keynesis::key::ed25519;
 use lru::LruCache;
-use std::sync::Arc;
+use std::{hash::Hash, sync::Arc};
+
+/// outcome of [`Profiles::put`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PutOutcome {
+    /// the profile was unknown, it has been added to the pool
+    Inserted,
+    /// the profile was known (in the trusted pool or in the pool) and the
+    /// new profile is more recent, it replaced the previous one
+    UpdatedNewer,
+    /// the profile was known (in the trusted pool or in the pool) and the
+    /// new profile is not more recent, it has been ignored
+    StaleIgnored,
+    /// the profile is in the dirty pool. It is replaced if the new profile
+    /// is more recent but it remains in the dirty pool
+    InDirty,
+}
+
+/// one of the pools of [`Profiles`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum PoolKind {
+    Trusted,
+    Pool,
+    Dirty,
+}
 
 pub struct Profiles {
     pub(crate) dirty: LruCache<ed25519::PublicKey, Arc<Profile>>,
@@ -30,6 +54,20 @@ impl Profiles {
         &self.trusted
     }
 
+    fn cache(&self, pool: PoolKind) -> &LruCache<ed25519::PublicKey, Arc<Profile>> {
+        match pool {
+            PoolKind::Trusted => &self.trusted,
+            PoolKind::Pool => &self.pool,
+            PoolKind::Dirty => &self.dirty,
+        }
+    }
+
+    /// the next profile to be evicted from the given pool (the least
+    /// recently used) without updating the LRU order of the entries
+    pub fn lru_candidate(&self, pool: PoolKind) -> Option<&ed25519::PublicKey> {
+        self.cache(pool).peek_lru().map(|(id, _)| id)
+    }
+
     pub fn promote(&mut self, entry: &ed25519::PublicKey) {
         if let Some(profile) = self.pool.pop(entry) {
             // if there is an overflow coming up, instead of losing
@@ -59,32 +97,73 @@ impl Profiles {
         }
     }
 
-    pub fn put(&mut self, id: ed25519::PublicKey, profile: Arc<Profile>) -> bool {
+    /// remove the profile from all the pools
+    pub fn forget(&mut self, id: &ed25519::PublicKey) -> Option<Arc<Profile>> {
+        let trusted = self.trusted.pop(id);
+        let pool = self.pool.pop(id);
+        let dirty = self.dirty.pop(id);
+
+        trusted.or(pool).or(dirty)
+    }
+
+    /// insert or update the profile
+    ///
+    /// the profile is only updated if it is more recent than the one
+    /// we already have. See [`PutOutcome`] for the details.
+    pub fn put(&mut self, id: ed25519::PublicKey, profile: Arc<Profile>) -> PutOutcome {
         if let Some(entry) = self.dirty.peek(&id).cloned() {
             if entry.last_update() < profile.last_update() {
                 self.dirty.put(id, profile);
             }
-            false
+            PutOutcome::InDirty
         } else if let Some(entry) = self.trusted.peek(&id).cloned() {
             if entry.last_update() < profile.last_update() {
                 self.trusted.put(id, profile);
-                true
+                PutOutcome::UpdatedNewer
             } else {
-                false
+                PutOutcome::StaleIgnored
             }
         } else if let Some(entry) = self.pool.peek(&id).cloned() {
             if entry.last_update() < profile.last_update() {
                 self.pool.put(id, profile);
-                true
+                PutOutcome::UpdatedNewer
             } else {
-                false
+                PutOutcome::StaleIgnored
             }
         } else {
             self.pool.put(id, profile);
-            true
+            PutOutcome::Inserted
         }
     }
 
+    /// number of profiles in all the pools
+    pub fn len(&self) -> usize {
+        self.trusted.len() + self.pool.len() + self.dirty.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// iterate through the profiles of all the pools (trusted, pool
+    /// and then dirty) without updating the LRU order of the entries
+    pub fn iter(&self) -> impl Iterator<Item = &'_ Arc<Profile>> {
+        self.trusted
+            .iter()
+            .chain(self.pool.iter())
+            .chain(self.dirty.iter())
+            .map(|(_, profile)| profile)
+    }
+
+    /// look for the profile in all the pools without updating
+    /// the LRU order of the entries
+    pub fn peek(&self, id: &ed25519::PublicKey) -> Option<&Arc<Profile>> {
+        self.trusted
+            .peek(id)
+            .or_else(|| self.pool.peek(id))
+            .or_else(|| self.dirty.peek(id))
+    }
+
     pub fn get(&mut self, id: &ed25519::PublicKey) -> Option<&Arc<Profile>> {
         if let Some(profile) = self.trusted.get(id) {
             Some(profile)
@@ -98,8 +177,115 @@ impl Profiles {
     }
 }
 
+/// copy the cache, keeping the LRU order of the entries
+pub(crate) fn clone_lru<K, V>(cache: &LruCache<K, V>) -> LruCache<K, V>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+{
+    let mut cloned = LruCache::new(cache.cap());
+    // the least recently used first
+    let entries: Vec<(&K, &V)> = cache.iter().collect();
+    for (key, value) in entries.into_iter().rev() {
+        cloned.put(key.clone(), value.clone());
+    }
+    cloned
+}
+
+impl Clone for Profiles {
+    /// the profiles are shared with the copy, only the pools are copied
+    fn clone(&self) -> Self {
+        Self {
+            dirty: clone_lru(&self.dirty),
+            pool: clone_lru(&self.pool),
+            trusted: clone_lru(&self.trusted),
+        }
+    }
+}
+
 impl Default for Profiles {
     fn default() -> Self {
         Self::new(512, 256, 128)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Gossip, Subscriptions};
+    use keynesis::{passport::block::Time, Seed};
+    use std::net::SocketAddr;
+
+    fn profile(id: &ed25519::SecretKey, time: u32) -> Arc<Profile> {
+        let address: SocketAddr = "127.0.0.1:9876".parse().unwrap();
+        let gossip = Gossip::new_at(
+            address,
+            id,
+            Subscriptions::new().as_slice(),
+            Time::from(time),
+        );
+        Arc::new(Profile::from_gossip(gossip))
+    }
+
+    #[test]
+    fn put_outcomes() {
+        let mut profiles = Profiles::default();
+        let id = ed25519::SecretKey::new(Seed::from([1; Seed::SIZE]).into_rand_chacha());
+        let pk = id.public_key();
+
+        assert_eq!(profiles.put(pk, profile(&id, 10)), PutOutcome::Inserted);
+        assert_eq!(profiles.put(pk, profile(&id, 20)), PutOutcome::UpdatedNewer);
+        assert_eq!(profiles.put(pk, profile(&id, 15)), PutOutcome::StaleIgnored);
+        assert_eq!(profiles.put(pk, profile(&id, 20)), PutOutcome::StaleIgnored);
+
+        profiles.demote(&pk);
+        assert_eq!(profiles.put(pk, profile(&id, 30)), PutOutcome::InDirty);
+        assert_eq!(profiles.peek(&pk).unwrap().last_update(), Time::from(30));
+        assert!(profiles.dirty().contains(&pk));
+    }
+
+    #[test]
+    fn lru_candidate() {
+        let mut profiles = Profiles::new(2, 3, 2);
+        let ids: Vec<_> = (1..5)
+            .map(|seed| ed25519::SecretKey::new(Seed::from([seed; Seed::SIZE]).into_rand_chacha()))
+            .collect();
+
+        assert_eq!(profiles.lru_candidate(PoolKind::Pool), None);
+
+        for id in ids.iter().take(3) {
+            profiles.put(id.public_key(), profile(id, 1));
+        }
+        assert_eq!(
+            profiles.lru_candidate(PoolKind::Pool),
+            Some(&ids[0].public_key())
+        );
+
+        // accessing the oldest entry makes the next one the candidate
+        profiles.get(&ids[0].public_key());
+        assert_eq!(
+            profiles.lru_candidate(PoolKind::Pool),
+            Some(&ids[1].public_key())
+        );
+        // but peeking does not change the order
+        profiles.peek(&ids[1].public_key());
+        assert_eq!(
+            profiles.lru_candidate(PoolKind::Pool),
+            Some(&ids[1].public_key())
+        );
+
+        profiles.put(ids[3].public_key(), profile(&ids[3], 1));
+        assert!(!profiles.pool().contains(&ids[1].public_key()));
+        assert_eq!(
+            profiles.lru_candidate(PoolKind::Pool),
+            Some(&ids[2].public_key())
+        );
+
+        profiles.promote(&ids[0].public_key());
+        assert_eq!(
+            profiles.lru_candidate(PoolKind::Trusted),
+            Some(&ids[0].public_key())
+        );
+        assert_eq!(profiles.lru_candidate(PoolKind::Dirty), None);
+    }
+}
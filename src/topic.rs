@@ -1,8 +1,11 @@
 use std::{
+    collections::BTreeSet,
     convert::{TryFrom, TryInto as _},
     fmt::{self, Formatter},
+    hash::{Hash, Hasher},
     iter::{DoubleEndedIterator, ExactSizeIterator, FusedIterator, Iterator},
     str::FromStr,
+    time::Duration,
 };
 use thiserror::Error;
 
@@ -16,9 +19,18 @@ pub struct InterestLevel(u8);
 #[derive(Clone, Copy)]
 pub struct SubscriptionSlice<'a>(&'a [u8]);
 
-#[derive(Clone, Copy)]
+/// a topic and the interest level for it
+///
+/// subscriptions are compared by topic first and then by interest level,
+/// use [`Subscription::same_topic`] to ignore the interest level.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Subscription([u8; Self::SIZE]);
 
+/// packed list of [`Subscription`]
+///
+/// The order in which the subscriptions are packed is not semantically
+/// meaningful: two `Subscriptions` holding the same set of subscriptions
+/// are equal (and have the same hash) regardless of their order.
 #[derive(Clone)]
 pub struct Subscriptions(Vec<u8>);
 
@@ -40,6 +52,9 @@ pub enum SubscriptionError {
         Subscriptions::MAX_NUM_SUBSCRIPTIONS
     )]
     MaxSubscriptionReached,
+
+    #[error("Buffer too small, expected at least {expected} bytes")]
+    BufferTooSmall { expected: usize },
 }
 
 impl Topic {
@@ -48,6 +63,20 @@ impl Topic {
     pub const fn new(topic: [u8; Self::SIZE]) -> Self {
         Self(topic)
     }
+
+    /// parse a list of hex encoded topics separated by new lines or commas
+    ///
+    /// the whitespaces around the topics and the empty entries are ignored.
+    /// On error, returns the index of the invalid entry in the list (the
+    /// empty entries included).
+    pub fn parse_list(s: &str) -> Result<Vec<Self>, (usize, hex::FromHexError)> {
+        s.split(|c| c == '\n' || c == ',')
+            .map(str::trim)
+            .enumerate()
+            .filter(|(_, entry)| !entry.is_empty())
+            .map(|(index, entry)| entry.parse().map_err(|error| (index, error)))
+            .collect()
+    }
 }
 
 impl InterestLevel {
@@ -71,6 +100,42 @@ impl InterestLevel {
     pub fn no_interest(self) -> bool {
         self == Self::ZERO
     }
+
+    /// the interest level as a weight between `0.0` and `1.0`
+    pub fn as_fraction(self) -> f32 {
+        self.0 as f32 / u8::MAX as f32
+    }
+
+    /// the interest level for a weight between `0.0` and `1.0`
+    ///
+    /// the weight is clamped to this range and rounded to the
+    /// closest level. `NaN` maps to [`InterestLevel::ZERO`].
+    pub fn from_fraction(fraction: f32) -> Self {
+        if fraction.is_nan() {
+            return Self::ZERO;
+        }
+        let fraction = fraction.clamp(0.0, 1.0);
+
+        Self((fraction * u8::MAX as f32).round() as u8)
+    }
+
+    /// compute the interest level after `elapsed` time of inactivity
+    ///
+    /// the interest is halved every `half_life` and eventually reaches
+    /// [`InterestLevel::ZERO`]. The level never increases.
+    pub fn decayed(self, elapsed: Duration, half_life: Duration) -> Self {
+        if elapsed == Duration::from_secs(0) {
+            return self;
+        }
+        if half_life == Duration::from_secs(0) {
+            return Self::ZERO;
+        }
+
+        let halvings = elapsed.as_secs_f64() / half_life.as_secs_f64();
+        let level = (self.0 as f64 * 0.5f64.powf(halvings)).floor();
+
+        Self(level as u8)
+    }
 }
 
 impl Subscription {
@@ -96,6 +161,12 @@ impl Subscription {
     pub fn interest_level(&self) -> InterestLevel {
         self.as_slice().interest_level()
     }
+
+    /// check both subscriptions are for the same topic, regardless
+    /// of their interest level
+    pub fn same_topic(&self, other: &Self) -> bool {
+        self.topic() == other.topic()
+    }
 }
 
 impl<'a> SubscriptionSlice<'a> {
@@ -133,14 +204,58 @@ impl<'a> SubscriptionSlice<'a> {
 impl Subscriptions {
     pub const MAX_NUM_SUBSCRIPTIONS: usize = 0b0000_0011_1111_1111; // 1023
 
+    /// number of subscriptions reserved by [`Subscriptions::new`]
+    const DEFAULT_CAPACITY: usize = 16;
+
     pub fn new() -> Self {
-        Self(Vec::with_capacity(
-            Self::MAX_NUM_SUBSCRIPTIONS * Subscription::SIZE,
-        ))
+        Self::with_capacity(Self::DEFAULT_CAPACITY)
+    }
+
+    /// create an empty `Subscriptions` with enough room to hold `capacity`
+    /// subscriptions without reallocating. The capacity is bounded to
+    /// [`Subscriptions::MAX_NUM_SUBSCRIPTIONS`].
+    ///
+    /// It is still possible to push more subscriptions, the buffer will
+    /// grow as needed.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let capacity = std::cmp::min(capacity, Self::MAX_NUM_SUBSCRIPTIONS);
+        Self(Vec::with_capacity(capacity * Subscription::SIZE))
+    }
+
+    /// number of subscriptions that can be held without reallocating
+    pub fn capacity(&self) -> usize {
+        self.0.capacity() / Subscription::SIZE
+    }
+
+    /// number of subscriptions
+    pub fn len(&self) -> usize {
+        self.as_slice().number_subscriptions()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// size in bytes of the packed subscriptions
+    pub fn byte_len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// copy the packed subscriptions at the beginning of `buf`
+    ///
+    /// returns the number of bytes written (see [`Subscriptions::byte_len`])
+    pub fn pack_into(&self, buf: &mut [u8]) -> Result<usize, SubscriptionError> {
+        let len = self.byte_len();
+        if buf.len() < len {
+            return Err(SubscriptionError::BufferTooSmall { expected: len });
+        }
+
+        buf[..len].copy_from_slice(&self.0);
+        Ok(len)
     }
 
     pub fn push(&mut self, sub: SubscriptionSlice<'_>) -> Result<(), SubscriptionError> {
-        if self.as_slice().number_subscriptions() >= Self::MAX_NUM_SUBSCRIPTIONS {
+        if self.len() >= Self::MAX_NUM_SUBSCRIPTIONS {
             return Err(SubscriptionError::MaxSubscriptionReached);
         }
 
@@ -149,6 +264,18 @@ impl Subscriptions {
         Ok(())
     }
 
+    /// remove all the subscriptions, keeping the allocated capacity
+    pub fn clear(&mut self) {
+        self.0.clear()
+    }
+
+    /// keep only the first `len` subscriptions
+    ///
+    /// this has no effect if there are already `len` or less subscriptions
+    pub fn truncate(&mut self, len: usize) {
+        self.0.truncate(len * Subscription::SIZE)
+    }
+
     pub fn as_slice(&self) -> SubscriptionsSlice<'_> {
         SubscriptionsSlice(self.0.as_ref())
     }
@@ -156,6 +283,72 @@ impl Subscriptions {
     pub fn iter(&self) -> SubscriptionIter<'_> {
         self.as_slice().iter()
     }
+
+    /// iterate over the subscribed topics only
+    pub fn iter_topics(&self) -> impl Iterator<Item = Topic> + '_ {
+        self.as_slice().iter_topics()
+    }
+
+    /// call `f` with every subscribed topic, in order
+    ///
+    /// same as [`Subscriptions::iter_topics`] but decodes the topics directly
+    /// from the packed subscriptions
+    pub fn for_each_topic<F>(&self, f: F)
+    where
+        F: FnMut(Topic),
+    {
+        self.as_slice().for_each_topic(f)
+    }
+
+    /// iterate over the subscribed topics and their interest levels
+    pub fn iter_levels(&self) -> impl Iterator<Item = (Topic, InterestLevel)> + '_ {
+        self.as_slice().iter_levels()
+    }
+
+    /// sort the subscriptions by topic and remove the duplicated topics,
+    /// keeping the highest interest level of a topic
+    ///
+    /// see [`SubscriptionsSlice::is_canonical`]
+    pub fn canonicalize(&mut self) {
+        let mut subscriptions: Vec<Subscription> = self
+            .iter()
+            .map(|subscription| subscription.to_owned())
+            .collect();
+        subscriptions.sort_by(|a, b| {
+            a.topic()
+                .cmp(&b.topic())
+                .then_with(|| b.interest_level().cmp(&a.interest_level()))
+        });
+        subscriptions.dedup_by_key(|subscription| subscription.topic());
+
+        self.0.clear();
+        for subscription in subscriptions {
+            self.0.extend_from_slice(subscription.as_slice().as_ref());
+        }
+    }
+
+    /// compare with `other`, returns the subscriptions that have been
+    /// `(added, removed)` since `other`, sorted
+    ///
+    /// A change of interest level for a topic shows up as the new
+    /// subscription being added and the old one removed.
+    pub fn diff(&self, other: &Self) -> (Vec<Subscription>, Vec<Subscription>) {
+        let current: BTreeSet<Subscription> = self.iter().map(|sub| sub.to_owned()).collect();
+        let previous: BTreeSet<Subscription> = other.iter().map(|sub| sub.to_owned()).collect();
+
+        (
+            current.difference(&previous).copied().collect(),
+            previous.difference(&current).copied().collect(),
+        )
+    }
+
+    /// the packed subscriptions, sorted and deduplicated
+    fn canonical(&self) -> Vec<&[u8]> {
+        let mut subscriptions: Vec<&[u8]> = self.0.chunks(Subscription::SIZE).collect();
+        subscriptions.sort_unstable();
+        subscriptions.dedup();
+        subscriptions
+    }
 }
 
 impl<'a> SubscriptionsSlice<'a> {
@@ -200,6 +393,43 @@ impl<'a> SubscriptionsSlice<'a> {
         SubscriptionIter(self)
     }
 
+    /// check the subscriptions are sorted by topic and that there is no
+    /// duplicated topic
+    pub fn is_canonical(self) -> bool {
+        let mut topics = self.iter_topics();
+        let mut previous = match topics.next() {
+            None => return true,
+            Some(topic) => topic,
+        };
+        for topic in topics {
+            if topic <= previous {
+                return false;
+            }
+            previous = topic;
+        }
+        true
+    }
+
+    pub fn for_each_topic<F>(self, mut f: F)
+    where
+        F: FnMut(Topic),
+    {
+        for chunk in self.0.chunks_exact(Subscription::SIZE) {
+            let mut topic = [0; Topic::SIZE];
+            topic.copy_from_slice(&chunk[..Topic::SIZE]);
+            f(Topic(topic))
+        }
+    }
+
+    pub fn iter_topics(self) -> impl Iterator<Item = Topic> + 'a {
+        self.iter().map(|subscription| subscription.topic())
+    }
+
+    pub fn iter_levels(self) -> impl Iterator<Item = (Topic, InterestLevel)> + 'a {
+        self.iter()
+            .map(|subscription| (subscription.topic(), subscription.interest_level()))
+    }
+
     pub fn pop_front(&mut self) -> Option<SubscriptionSlice<'a>> {
         let obj = self.get(0)?;
 
@@ -209,17 +439,17 @@ impl<'a> SubscriptionsSlice<'a> {
     }
 
     pub fn pop_back(&mut self) -> Option<SubscriptionSlice<'a>> {
-        let index = self.number_subscriptions();
+        let index = self.number_subscriptions().checked_sub(1)?;
         let sub = self.get(index)?;
 
-        self.0 = &self.0[..index];
+        self.0 = &self.0[..self.subscription_offset(index)];
 
         Some(sub)
     }
 
     pub fn get(self, index: usize) -> Option<SubscriptionSlice<'a>> {
         let len = self.number_subscriptions();
-        if len == 0 || len < index {
+        if index >= len {
             None
         } else {
             let index = self.subscription_offset(index);
@@ -239,9 +469,25 @@ impl Default for Subscriptions {
     }
 }
 
+/* Eq ********************************************************************** */
+
+impl PartialEq for Subscriptions {
+    fn eq(&self, other: &Self) -> bool {
+        self.canonical() == other.canonical()
+    }
+}
+
+impl Eq for Subscriptions {}
+
+impl Hash for Subscriptions {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.canonical().hash(state)
+    }
+}
+
 /* Convert ***************************************************************** */
 
-impl<'a> TryFrom<&'a [u8]> for Topic {
+impl TryFrom<&[u8]> for Topic {
     type Error = std::array::TryFromSliceError;
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
         let bytes = value.try_into()?;
@@ -425,6 +671,25 @@ mod tests {
         }
     }
 
+    #[test]
+    fn subscriptions_with_capacity() {
+        let mut subs = Subscriptions::with_capacity(4);
+        let mut g = quickcheck::Gen::new(1024);
+        let g = &mut g;
+
+        assert!(subs.capacity() >= 4);
+        assert!(subs.capacity() < Subscriptions::new().capacity());
+
+        for _ in 0..Subscriptions::MAX_NUM_SUBSCRIPTIONS {
+            subs.push(Subscription::arbitrary(g).as_slice())
+                .expect("the buffer should grow up to the max number of subscriptions");
+        }
+        assert_eq!(
+            subs.as_slice().number_subscriptions(),
+            Subscriptions::MAX_NUM_SUBSCRIPTIONS
+        );
+    }
+
     /// make sure we are reaching an error if we are creating a subscriptions with too
     /// many entries
     #[test]
@@ -460,6 +725,219 @@ mod tests {
             .expect_err("Should have a max size reached error");
     }
 
+    #[test]
+    fn subscriptions_clear_truncate() {
+        let mut subs = Subscriptions::new();
+        let mut g = quickcheck::Gen::new(1024);
+        let g = &mut g;
+
+        let entries: Vec<Subscription> = (0..5).map(|_| Subscription::arbitrary(g)).collect();
+        for sub in entries.iter() {
+            subs.push(sub.as_slice()).unwrap();
+        }
+
+        subs.truncate(2);
+        assert_eq!(subs.len(), 2);
+        for (sub, expected) in subs.iter().zip(entries.iter()) {
+            assert_eq!(sub.as_ref(), expected.as_ref());
+        }
+
+        subs.clear();
+        assert!(subs.is_empty());
+    }
+
+    #[test]
+    fn subscriptions_len() {
+        let mut subs = Subscriptions::new();
+        assert_eq!(subs.len(), 0);
+        assert!(subs.is_empty());
+        assert_eq!(subs.byte_len(), 0);
+
+        let mut g = quickcheck::Gen::new(1024);
+        for _ in 0..4 {
+            subs.push(Subscription::arbitrary(&mut g).as_slice())
+                .unwrap();
+        }
+        assert_eq!(subs.len(), 4);
+        assert!(!subs.is_empty());
+        assert_eq!(subs.byte_len(), 4 * Subscription::SIZE);
+    }
+
+    #[test]
+    fn subscriptions_pack_into() {
+        let mut subs = Subscriptions::new();
+        let mut g = quickcheck::Gen::new(1024);
+        for _ in 0..3 {
+            subs.push(Subscription::arbitrary(&mut g).as_slice())
+                .unwrap();
+        }
+
+        let mut buf = vec![0; subs.byte_len()];
+        assert_eq!(subs.pack_into(&mut buf).unwrap(), subs.byte_len());
+        assert_eq!(buf.as_slice(), subs.as_slice().as_ref());
+
+        let mut buf = vec![0; subs.byte_len() - 1];
+        assert!(matches!(
+            subs.pack_into(&mut buf),
+            Err(SubscriptionError::BufferTooSmall { expected }) if expected == subs.byte_len()
+        ));
+    }
+
+    #[test]
+    fn subscriptions_iter_topics() {
+        let topics: Vec<Topic> = (1..4).map(|i| Topic::new([i; Topic::SIZE])).collect();
+        let mut subs = Subscriptions::new();
+        for (i, topic) in topics.iter().enumerate() {
+            subs.push(Subscription::new(*topic, InterestLevel::new(i as u8)).as_slice())
+                .unwrap();
+        }
+
+        assert_eq!(subs.iter_topics().collect::<Vec<_>>(), topics);
+        assert!(subs.iter_topics().eq(subs.iter().map(|s| s.topic())));
+
+        let mut visited = Vec::new();
+        subs.for_each_topic(|topic| visited.push(topic));
+        assert_eq!(visited, topics);
+
+        let levels: Vec<(Topic, InterestLevel)> = subs.iter_levels().collect();
+        assert_eq!(levels[2], (topics[2], InterestLevel::new(2)));
+    }
+
+    #[test]
+    fn subscription_same_topic_and_ord() {
+        let topic1 = Topic::new([1; Topic::SIZE]);
+        let topic2 = Topic::new([2; Topic::SIZE]);
+
+        let low = Subscription::new(topic1, InterestLevel::new(1));
+        let high = Subscription::new(topic1, InterestLevel::new(2));
+        let other = Subscription::new(topic2, InterestLevel::new(0));
+
+        assert!(low.same_topic(&high));
+        assert_ne!(low, high);
+        assert!(!low.same_topic(&other));
+
+        let mut sorted = vec![other, high, low];
+        sorted.sort();
+        assert_eq!(sorted, vec![low, high, other]);
+    }
+
+    #[test]
+    fn subscriptions_diff() {
+        let subscription = |byte: u8, level: u8| {
+            Subscription::new(Topic::new([byte; Topic::SIZE]), InterestLevel::new(level))
+        };
+        let subscriptions_of = |entries: &[Subscription]| {
+            let mut subs = Subscriptions::new();
+            for entry in entries {
+                subs.push(entry.as_slice()).unwrap();
+            }
+            subs
+        };
+
+        let previous =
+            subscriptions_of(&[subscription(1, 1), subscription(2, 1), subscription(3, 1)]);
+        let current =
+            subscriptions_of(&[subscription(4, 1), subscription(2, 5), subscription(1, 1)]);
+
+        let (added, removed) = current.diff(&previous);
+        assert_eq!(added, vec![subscription(2, 5), subscription(4, 1)]);
+        assert_eq!(removed, vec![subscription(2, 1), subscription(3, 1)]);
+
+        let (added, removed) = current.diff(&current);
+        assert!(added.is_empty());
+        assert!(removed.is_empty());
+    }
+
+    #[test]
+    fn subscriptions_iter_rev() {
+        let topics: Vec<Topic> = (1..4).map(|i| Topic::new([i; Topic::SIZE])).collect();
+        let mut subs = Subscriptions::new();
+        for topic in topics.iter() {
+            subs.push(Subscription::new(*topic, InterestLevel::new(1)).as_slice())
+                .unwrap();
+        }
+
+        let reversed: Vec<Topic> = subs.iter().rev().map(|sub| sub.topic()).collect();
+        assert_eq!(reversed, topics.iter().rev().copied().collect::<Vec<_>>());
+        assert!(subs.as_slice().get(3).is_none());
+    }
+
+    #[test]
+    fn subscriptions_eq_ignores_order() {
+        let sub1 = Subscription::new(Topic::new([1; Topic::SIZE]), InterestLevel::new(1));
+        let sub2 = Subscription::new(Topic::new([2; Topic::SIZE]), InterestLevel::new(2));
+
+        let mut subs1 = Subscriptions::new();
+        subs1.push(sub1.as_slice()).unwrap();
+        subs1.push(sub2.as_slice()).unwrap();
+
+        let mut subs2 = Subscriptions::new();
+        subs2.push(sub2.as_slice()).unwrap();
+        subs2.push(sub1.as_slice()).unwrap();
+
+        assert_eq!(subs1, subs2);
+
+        let hash = |subs: &Subscriptions| {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            subs.hash(&mut hasher);
+            hasher.finish()
+        };
+        assert_eq!(hash(&subs1), hash(&subs2));
+
+        let mut subs3 = Subscriptions::new();
+        subs3.push(sub1.as_slice()).unwrap();
+        assert_ne!(subs1, subs3);
+    }
+
+    #[test]
+    fn interest_level_decayed() {
+        let level = InterestLevel::new(200);
+        let half_life = Duration::from_secs(60);
+
+        assert_eq!(level.decayed(Duration::from_secs(0), half_life), level);
+        assert_eq!(
+            level.decayed(Duration::from_secs(60), half_life),
+            InterestLevel::new(100)
+        );
+        assert_eq!(
+            level.decayed(Duration::from_secs(60 * 60), half_life),
+            InterestLevel::ZERO
+        );
+    }
+
+    #[test]
+    fn interest_level_fraction() {
+        assert_eq!(InterestLevel::from_fraction(0.0), InterestLevel::ZERO);
+        assert_eq!(InterestLevel::from_fraction(1.0), InterestLevel::new(255));
+        assert_eq!(InterestLevel::from_fraction(-1.0), InterestLevel::ZERO);
+        assert_eq!(InterestLevel::from_fraction(2.0), InterestLevel::new(255));
+        assert_eq!(InterestLevel::from_fraction(f32::NAN), InterestLevel::ZERO);
+
+        assert_eq!(InterestLevel::ZERO.as_fraction(), 0.0);
+        assert_eq!(InterestLevel::new(255).as_fraction(), 1.0);
+
+        let level = InterestLevel::from_fraction(0.5);
+        assert_eq!(level, InterestLevel::new(128));
+        assert!((level.as_fraction() - 0.5).abs() < 1.0 / 255.0);
+    }
+
+    #[quickcheck]
+    fn interest_level_fraction_roundtrip(level: InterestLevel) -> bool {
+        InterestLevel::from_fraction(level.as_fraction()) == level
+    }
+
+    #[quickcheck]
+    fn interest_level_decayed_never_increases(
+        level: InterestLevel,
+        elapsed: u32,
+        half_life: u32,
+    ) -> bool {
+        let elapsed = Duration::from_secs(elapsed as u64);
+        let half_life = Duration::from_secs(half_life as u64);
+
+        level.decayed(elapsed, half_life) <= level
+    }
+
     #[test]
     fn topic_from_str() {
         let topic = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
@@ -467,6 +945,30 @@ mod tests {
         let _topic = Topic::from_str(topic).unwrap();
     }
 
+    #[test]
+    fn topic_parse_list() {
+        let t1 = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+        let t2 = "fedcba9876543210fedcba9876543210fedcba9876543210fedcba9876543210";
+        let expected = vec![Topic::from_str(t1).unwrap(), Topic::from_str(t2).unwrap()];
+
+        let list = format!("{}\n  {}\n\n", t1, t2);
+        assert_eq!(Topic::parse_list(&list).unwrap(), expected);
+        let list = format!("{}, {}", t1, t2);
+        assert_eq!(Topic::parse_list(&list).unwrap(), expected);
+        assert_eq!(Topic::parse_list("").unwrap(), Vec::new());
+
+        let list = format!("{}\n{}\ng{}\n{}", t1, t2, &t1[1..], t1);
+        assert!(matches!(
+            Topic::parse_list(&list),
+            Err((2, hex::FromHexError::InvalidHexCharacter { .. }))
+        ));
+        let list = format!("{},,{}", t1, &t2[..10]);
+        assert!(matches!(
+            Topic::parse_list(&list),
+            Err((2, hex::FromHexError::InvalidStringLength))
+        ));
+    }
+
     #[test]
     fn topic_to_string() {
         let topic = [
@@ -1,15 +1,144 @@
 use crate::{
     layer::{self, Layer, LayerBuilder, Selection, ViewBuilder},
-    Gossip, Profile, Profiles, Topic,
+    profiles::{clone_lru, PutOutcome},
+    Gossip, GossipError, GossipSlice, Profile, Profiles, SubscriptionError, Subscriptions, Topic,
 };
-use keynesis::key::ed25519;
-use std::{net::SocketAddr, sync::Arc};
+use keynesis::{key::ed25519, passport::block::Time};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+use thiserror::Error;
 
 pub struct Topology {
     view_layers: Vec<Box<dyn Layer>>,
     gossip_layers: Vec<Box<dyn Layer>>,
     profile: Profile,
     profiles: Profiles,
+    small_pool_threshold: usize,
+    view_cache: lru::LruCache<(Option<ed25519::PublicKey>, Selection), CachedView>,
+    topics_usage: HashMap<Topic, Time>,
+    banned: lru::LruCache<ed25519::PublicKey, ()>,
+    subscribed_topics: HashSet<Topic>,
+    seeds: Vec<ed25519::PublicKey>,
+    address_preference: AddressPreference,
+    ingest_rate_limit: Option<(u32, Duration)>,
+    ingest_usage: lru::LruCache<ed25519::PublicKey, IngestUsage>,
+    include_self: bool,
+    gossip_max_age: Option<Duration>,
+}
+
+/// the nodes every view layer added to a view, in the order of the layers
+///
+/// `None` for the layers whose view cannot be cached (see
+/// [`Layer::is_stable`]) or has not been computed yet
+type CachedView = Vec<Option<Vec<(ed25519::PublicKey, Selection)>>>;
+
+/// the gossips received from a peer in the current rate limit window,
+/// see [`Topology::set_ingest_rate_limit`]
+#[derive(Clone)]
+struct IngestUsage {
+    window_start: Time,
+    count: u32,
+    strikes: u32,
+}
+
+/// outcome of importing a gossip with [`Topology::import_gossip`]
+#[derive(Debug)]
+pub enum ImportOutcome {
+    /// the gossip was for a peer we did not know about
+    New,
+    /// the gossip was newer than the one we had for this peer
+    Updated,
+    /// we already had this gossip (or a more recent one), the peer is
+    /// in the dirty pool, the peer is banned or the gossip is our own
+    Unchanged,
+    /// the gossip is not valid and has been ignored
+    Rejected(GossipError),
+    /// the sender already sent too many gossips, see
+    /// [`Topology::set_ingest_rate_limit`]
+    RateLimited,
+}
+
+/// a profile selected in the view, see [`Topology::detailed_view`]
+#[derive(Clone)]
+pub struct ViewEntry {
+    pub profile: Arc<Profile>,
+    /// the selection that made this profile part of the view. This is
+    /// the topic of the ring if the profile is a ring neighbor of ours,
+    /// [`Selection::Any`] otherwise.
+    pub via: Selection,
+}
+
+/// how much the view layers select the same peers, see
+/// [`Topology::layer_overlap`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OverlapReport {
+    /// the number of peers selected by each layer, in the order of
+    /// the layers
+    pub layers: Vec<(&'static str, usize)>,
+    /// the number of peers selected by both layers, for every pair of
+    /// layers
+    pub overlaps: Vec<(&'static str, &'static str, usize)>,
+}
+
+impl OverlapReport {
+    /// the number of peers selected by both layers (in any order)
+    pub fn overlap(&self, a: &str, b: &str) -> Option<usize> {
+        self.overlaps
+            .iter()
+            .find(|(first, second, _)| {
+                (*first == a && *second == b) || (*first == b && *second == a)
+            })
+            .map(|(_, _, count)| *count)
+    }
+}
+
+/// the order of the profiles returned by [`Topology::view_ordered`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ViewOrdering {
+    /// the order in which the layers selected the profiles
+    LayerPriority,
+    /// the most recently updated profiles first
+    FreshnessDesc,
+    /// the profiles the closest to ours first
+    ProximityDesc,
+}
+
+/// an inconsistency of the internal state, see [`Topology::validate`]
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ConsistencyError {
+    #[error("The layer {layer} references the unknown peer {id}")]
+    DanglingLayerKey {
+        layer: &'static str,
+        id: ed25519::PublicKey,
+    },
+
+    #[error("The peer {id} is in more than one profile pool")]
+    MultiplePools { id: ed25519::PublicKey },
+
+    #[error("Our own profile is in the profile pools")]
+    OwnProfileInPool,
+}
+
+/// our neighbors in the ring of a topic, see [`Topology::ring_neighbors`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RingNeighbors {
+    /// the neighbors with a lower id than ours, sorted
+    pub predecessors: Vec<ed25519::PublicKey>,
+    /// the neighbors with a higher id than ours, sorted
+    pub successors: Vec<ed25519::PublicKey>,
+}
+
+/// the IP family to dial first, see [`Topology::set_address_preference`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AddressPreference {
+    Ipv4First,
+    Ipv6First,
+    NoPreference,
 }
 
 struct DefaultBuilder;
@@ -33,6 +162,14 @@ impl LayerBuilder for DefaultBuilder {
 }
 
 impl Topology {
+    /// maximum number of peers kept in the blocklist, see [`Topology::ban_peer`]
+    pub const MAX_BANNED_PEERS: usize = 256;
+    /// maximum number of senders tracked by the rate limit, see
+    /// [`Topology::set_ingest_rate_limit`]
+    pub const MAX_RATE_LIMITED_PEERS: usize = 512;
+    /// maximum number of views kept in the cache, see [`Topology::view`]
+    pub const MAX_CACHED_VIEWS: usize = 64;
+
     /// create a Topology for the given profile
     pub fn new(address: SocketAddr, id: &ed25519::SecretKey) -> Self {
         Self::new_with(address, id, DefaultBuilder)
@@ -49,7 +186,104 @@ impl Topology {
 
             profile,
             profiles: Profiles::new(512, 256, 128),
+            small_pool_threshold: 0,
+            view_cache: lru::LruCache::new(Self::MAX_CACHED_VIEWS),
+            topics_usage: HashMap::new(),
+            banned: lru::LruCache::new(Self::MAX_BANNED_PEERS),
+            ingest_rate_limit: None,
+            ingest_usage: lru::LruCache::new(Self::MAX_RATE_LIMITED_PEERS),
+            include_self: false,
+            gossip_max_age: None,
+            subscribed_topics: HashSet::new(),
+            seeds: Vec::new(),
+            address_preference: AddressPreference::NoPreference,
+        }
+    }
+
+    /// create a Topology and join the network
+    ///
+    /// This subscribes to all the given topics (the topics past
+    /// [`Subscriptions::MAX_NUM_SUBSCRIPTIONS`] are ignored), imports the
+    /// seeds' gossips (the invalid ones are ignored) and commits our profile. The returned
+    /// gossips are the announcement of our node to send to the seeds.
+    pub fn join<T, S>(
+        address: SocketAddr,
+        id: &ed25519::SecretKey,
+        topics: T,
+        seeds: S,
+    ) -> (Self, Vec<Gossip>)
+    where
+        T: IntoIterator<Item = Topic>,
+        S: IntoIterator<Item = Gossip>,
+    {
+        let mut topology = Self::new(address, id);
+
+        for topic in topics {
+            if topology.subscribe_topic(topic).is_err() {
+                break;
+            }
         }
+
+        for seed in seeds {
+            let id = seed.id();
+            match topology.import_gossip(seed) {
+                ImportOutcome::New | ImportOutcome::Updated => topology.seeds.push(id),
+                ImportOutcome::Unchanged
+                | ImportOutcome::Rejected(_)
+                | ImportOutcome::RateLimited => (),
+            }
+        }
+
+        topology.update_profile_subscriptions(id);
+
+        let gossips = vec![topology.self_gossip().clone()];
+
+        (topology, gossips)
+    }
+
+    /// create an independent copy of the topology, for simulations
+    ///
+    /// the profiles are shared with the copy (they are immutable) but the
+    /// pools, the layers and the settings are copied: modifying the copy
+    /// does not affect `self`. Returns `None` if one of the layers cannot
+    /// be forked (see [`Layer::fork`]).
+    pub fn fork(&self) -> Option<Topology> {
+        let fork_layers = |layers: &[Box<dyn Layer>]| -> Option<Vec<Box<dyn Layer>>> {
+            layers.iter().map(|layer| layer.fork()).collect()
+        };
+
+        Some(Self {
+            view_layers: fork_layers(&self.view_layers)?,
+            gossip_layers: fork_layers(&self.gossip_layers)?,
+            profile: self.profile.clone(),
+            profiles: self.profiles.clone(),
+            small_pool_threshold: self.small_pool_threshold,
+            view_cache: clone_lru(&self.view_cache),
+            topics_usage: self.topics_usage.clone(),
+            banned: clone_lru(&self.banned),
+            subscribed_topics: self.subscribed_topics.clone(),
+            seeds: self.seeds.clone(),
+            address_preference: self.address_preference,
+            ingest_rate_limit: self.ingest_rate_limit,
+            ingest_usage: clone_lru(&self.ingest_usage),
+            include_self: self.include_self,
+            gossip_max_age: self.gossip_max_age,
+        })
+    }
+
+    /// the addresses of the seeds imported by [`Topology::join`], the
+    /// closest to our profile first (see [`Profile::proximity_to`])
+    ///
+    /// the seeds we have forgotten about since are skipped
+    pub fn seed_dial_order(&self) -> Vec<SocketAddr> {
+        let mut seeds: Vec<&Arc<Profile>> = self
+            .seeds
+            .iter()
+            .filter_map(|id| self.profiles.peek(id))
+            .collect();
+        seeds.sort_by_key(|seed| Reverse(self.profile.proximity_to(seed)));
+
+        seeds.into_iter().map(|seed| seed.address()).collect()
     }
 
     pub fn update_profile_subscriptions(&mut self, id: &ed25519::SecretKey) {
@@ -63,17 +297,61 @@ impl Topology {
 
     /// subscribe to the given topic
     ///
-    /// this function also update our profile
-    pub fn subscribe_topic(&mut self, topic: Topic) {
+    /// this function also update our profile. The layers are populated
+    /// with the peers we already know to be subscribed to this topic.
+    ///
+    /// Subscribing to more than [`Subscriptions::MAX_NUM_SUBSCRIPTIONS`]
+    /// topics fails with [`SubscriptionError::MaxSubscriptionReached`]: the
+    /// extra topics could not be advertised in our gossip.
+    ///
+    /// Returns `false` if we were already subscribed to the topic, in
+    /// which case nothing is changed and our gossip does not need to be
+    /// committed again.
+    pub fn subscribe_topic(&mut self, topic: Topic) -> Result<bool, SubscriptionError> {
+        if self.subscribed_topics.contains(&topic) {
+            return Ok(false);
+        }
+        if self.subscribed_topics.len() >= Subscriptions::MAX_NUM_SUBSCRIPTIONS {
+            return Err(SubscriptionError::MaxSubscriptionReached);
+        }
+        self.subscribed_topics.insert(topic);
+
+        self.invalidate_view_cache_for(&std::iter::once(topic).collect());
+
         for layer in self.view_layers.iter_mut() {
             layer.subscribe(topic);
         }
+
+        // the dirty pool is left out: the demoted peers are not in the layers
+        let subscribers = self
+            .profiles
+            .trusted()
+            .iter()
+            .chain(self.profiles.pool().iter())
+            .map(|(_, profile)| profile)
+            .filter(|profile| {
+                profile
+                    .gossip()
+                    .subscriptions()
+                    .iter_topics()
+                    .any(|subscribed| subscribed == topic)
+            });
+        for profile in subscribers {
+            for layer in self.view_layers.iter_mut() {
+                layer.populate(&self.profile, profile);
+            }
+        }
+
+        Ok(true)
     }
 
     /// unsubscribe to the given topic
     ///
     /// this function also update our profile
     pub fn unsubscribe_topic(&mut self, topic: &Topic) {
+        self.invalidate_view_cache_for(&std::iter::once(*topic).collect());
+        self.subscribed_topics.remove(topic);
+
         for layer in self.view_layers.iter_mut() {
             layer.unsubscribe(topic);
         }
@@ -81,6 +359,25 @@ impl Topology {
         self.profile.unsubscribe(topic);
     }
 
+    /// record that a message for the given topic has been received or sent
+    ///
+    /// see [`Topology::topic_last_used`]
+    pub fn observe_message(&mut self, topic: Topic) {
+        self.observe_message_at(topic, Time::now())
+    }
+
+    fn observe_message_at(&mut self, topic: Topic, time: Time) {
+        let last_use = self.topics_usage.entry(topic).or_insert(time);
+        if *last_use < time {
+            *last_use = time;
+        }
+    }
+
+    /// last time a message has been observed for the given topic
+    pub fn topic_last_used(&self, topic: &Topic) -> Option<Time> {
+        self.topics_usage.get(topic).copied()
+    }
+
     /// call this function if you could not establish an handshake from this
     /// peer. This will prevent to use it in the next profile update.
     ///
@@ -88,6 +385,8 @@ impl Topology {
     /// entirely from our profile pool. We may share it to other nodes
     /// we may find it relevant
     pub fn remove_peer(&mut self, id: &ed25519::PublicKey) {
+        self.invalidate_view_cache();
+
         for layer in self.view_layers.iter_mut() {
             layer.remove(id);
         }
@@ -95,11 +394,67 @@ impl Topology {
         self.profiles.demote(id);
     }
 
+    /// call this function when the connection with the given peer is closed
+    ///
+    /// A `clean` disconnect only removes the peer from our layers: the peer
+    /// stays in its profile pool and can be promoted again later. An unclean
+    /// disconnect is considered a failure to communicate with the peer and
+    /// the peer is also demoted (see [`Topology::remove_peer`]). Repeated
+    /// unclean disconnects will eventually move the peer to the dirty pool.
+    pub fn handle_disconnect(&mut self, id: &ed25519::PublicKey, clean: bool) {
+        if clean {
+            self.invalidate_view_cache();
+
+            for layer in self.view_layers.iter_mut() {
+                layer.remove(id);
+            }
+        } else {
+            self.remove_peer(id);
+        }
+    }
+
+    /// remove the peer from our layers and from the profile pools
+    ///
+    /// unlike [`Topology::remove_peer`] the peer will not be shared
+    /// with other nodes anymore.
+    pub fn forget_peer(&mut self, id: &ed25519::PublicKey) {
+        self.invalidate_view_cache();
+
+        for layer in self.view_layers.iter_mut() {
+            layer.remove(id);
+        }
+
+        self.profiles.forget(id);
+    }
+
+    /// forget the peer (see [`Topology::forget_peer`]) and refuse to add
+    /// it again until it is unbanned with [`Topology::unban_peer`]
+    ///
+    /// The blocklist holds up to [`Topology::MAX_BANNED_PEERS`] entries,
+    /// banning more peers will unban the least recently banned ones.
+    pub fn ban_peer(&mut self, id: &ed25519::PublicKey) {
+        self.forget_peer(id);
+        self.banned.put(*id, ());
+    }
+
+    /// remove the peer from the blocklist
+    ///
+    /// returns `false` if the peer was not banned
+    pub fn unban_peer(&mut self, id: &ed25519::PublicKey) -> bool {
+        self.banned.pop(id).is_some()
+    }
+
+    pub fn is_banned(&self, id: &ed25519::PublicKey) -> bool {
+        self.banned.contains(id)
+    }
+
     /// call this function to validate you were able to connect with the given
     /// peer. This will help the system make sure this entry is kept and reuse
     ///
     /// Call this function every time you successfully establish an handshake
     pub fn promote_peer(&mut self, id: &ed25519::PublicKey) {
+        self.invalidate_view_cache();
+
         self.profiles.promote(id)
     }
 
@@ -112,47 +467,434 @@ impl Topology {
     /// However, if the peer was already demoted some times (i.e. the peer was already
     /// known and we already know we cannot connect to it for now, it will be required
     /// to be "forgotten" or to be "promoted" in order to move away from the naughty
-    /// list).
+    /// list). Banned peers (see [`Topology::ban_peer`]) are always refused.
     pub fn add_peer(&mut self, peer: Profile) -> bool {
         let id = peer.id();
+        if self.is_banned(&id) {
+            return false;
+        }
+
+        match self.put_peer(peer) {
+            PutOutcome::Inserted | PutOutcome::UpdatedNewer => true,
+            PutOutcome::StaleIgnored | PutOutcome::InDirty => false,
+        }
+    }
+
+    /// insert or update the profile in the pools and, if the profile is
+    /// in use (not stale nor dirty), populate the layers with it
+    fn put_peer(&mut self, peer: Profile) -> PutOutcome {
+        let id = peer.id();
+
+        // the topics of the previous version of the profile: the cached
+        // views of these topics may hold the previous version
+        let mut topics: HashSet<Topic> = self
+            .profiles
+            .peek(&id)
+            .map(|known| known.gossip().subscriptions().iter_topics().collect())
+            .unwrap_or_default();
 
         let peer = Arc::new(peer);
 
-        if !self.profiles.put(id, Arc::clone(&peer)) {
-            return false;
+        let outcome = self.profiles.put(id, Arc::clone(&peer));
+        match outcome {
+            PutOutcome::Inserted | PutOutcome::UpdatedNewer => (),
+            PutOutcome::StaleIgnored | PutOutcome::InDirty => return outcome,
         }
 
+        topics.extend(peer.gossip().subscriptions().iter_topics());
+        self.invalidate_view_cache_for(&topics);
+
         for layer in self.view_layers.iter_mut() {
             layer.populate(&self.profile, &peer);
         }
 
-        true
+        outcome
+    }
+
+    /// import the given gossip into the Topology
+    ///
+    /// Unlike [`Topology::add_peer`], this function verifies the gossip and
+    /// tells precisely what has been done with it: was the peer unknown,
+    /// was it an update of a known peer or was it not useful at all.
+    pub fn import_gossip(&mut self, gossip: Gossip) -> ImportOutcome {
+        if let Err(error) = GossipSlice::try_from_slice(gossip.as_ref()) {
+            return ImportOutcome::Rejected(error);
+        }
+
+        let id = gossip.id();
+        if id == self.profile.id() || self.is_banned(&id) {
+            return ImportOutcome::Unchanged;
+        }
+
+        match self.put_peer(Profile::from_gossip(gossip)) {
+            PutOutcome::Inserted => ImportOutcome::New,
+            PutOutcome::UpdatedNewer => ImportOutcome::Updated,
+            PutOutcome::StaleIgnored | PutOutcome::InDirty => ImportOutcome::Unchanged,
+        }
+    }
+
+    /// only accept `per_peer` gossips from the same sender for every
+    /// `window` (with a granularity of a second)
+    ///
+    /// this applies to [`Topology::import_gossip_from`] and
+    /// [`Topology::update`]. The extra gossips are ignored with
+    /// [`ImportOutcome::RateLimited`] and the sender receives a strike
+    /// (see [`Topology::ingest_strikes`]). There is no limit by default.
+    pub fn set_ingest_rate_limit(&mut self, per_peer: u32, window: Duration) {
+        self.ingest_rate_limit = Some((per_peer, window));
+    }
+
+    /// number of rate limit windows in which the peer sent more gossips
+    /// than allowed, see [`Topology::set_ingest_rate_limit`]
+    pub fn ingest_strikes(&self, id: &ed25519::PublicKey) -> u32 {
+        self.ingest_usage
+            .peek(id)
+            .map(|usage| usage.strikes)
+            .unwrap_or(0)
+    }
+
+    /// same as [`Topology::import_gossip`] for a gossip received from
+    /// the peer `from`, applying the rate limit
+    pub fn import_gossip_from(
+        &mut self,
+        from: &ed25519::PublicKey,
+        gossip: Gossip,
+    ) -> ImportOutcome {
+        self.import_gossip_from_at(from, gossip, Time::now())
+    }
+
+    fn import_gossip_from_at(
+        &mut self,
+        from: &ed25519::PublicKey,
+        gossip: Gossip,
+        now: Time,
+    ) -> ImportOutcome {
+        if self.is_rate_limited(from, now) {
+            ImportOutcome::RateLimited
+        } else {
+            self.import_gossip(gossip)
+        }
+    }
+
+    /// count one more gossip from the sender and check if it is
+    /// above the rate limit
+    fn is_rate_limited(&mut self, from: &ed25519::PublicKey, now: Time) -> bool {
+        let (per_peer, window) = if let Some(limit) = self.ingest_rate_limit {
+            limit
+        } else {
+            return false;
+        };
+
+        if let Some(usage) = self.ingest_usage.get_mut(from) {
+            let elapsed = now
+                .seconds_since_covid_epoch()
+                .saturating_sub(usage.window_start.seconds_since_covid_epoch());
+            if u64::from(elapsed) >= window.as_secs() {
+                usage.window_start = now;
+                usage.count = 0;
+            }
+        } else {
+            self.ingest_usage.put(
+                *from,
+                IngestUsage {
+                    window_start: now,
+                    count: 0,
+                    strikes: 0,
+                },
+            );
+        }
+
+        let usage = self
+            .ingest_usage
+            .get_mut(from)
+            .expect("the usage has just been inserted");
+        if usage.count >= per_peer {
+            // only one strike per window
+            if usage.count == per_peer {
+                usage.strikes += 1;
+                usage.count += 1;
+            }
+            true
+        } else {
+            usage.count += 1;
+            false
+        }
+    }
+
+    /// import the profiles known by the `other` topology
+    ///
+    /// every profile of `other`'s trusted pool and pool (and its own
+    /// profile) is imported with [`Topology::import_gossip`]: the known
+    /// peers are only updated if `other` has a more recent gossip. The
+    /// peers `other` demoted to its dirty pool are not imported.
+    pub fn merge_peers_from(&mut self, other: &Topology) {
+        let ours = self.profile.id();
+        let gossips: Vec<Gossip> = other
+            .profiles
+            .trusted()
+            .iter()
+            .chain(other.profiles.pool().iter())
+            .map(|(_, profile)| profile.gossip())
+            .chain(std::iter::once(other.self_gossip()))
+            .filter(|gossip| gossip.id() != ours)
+            .cloned()
+            .collect();
+
+        for gossip in gossips {
+            self.import_gossip(gossip);
+        }
+    }
+
+    /// import the gossips received from the peer `from` and returns the
+    /// newly learned peers that made it into our view and share at least
+    /// one topic with us
+    ///
+    /// The peers we already knew about (including `from` itself) are not
+    /// returned: these are the new peers worth connecting to.
+    ///
+    /// If the gossips include a valid gossip of `from` itself, the sender
+    /// is alive and reachable: its profile is refreshed and it is promoted
+    /// (see [`Topology::promote_peer`]).
+    pub fn update<I>(&mut self, from: &ed25519::PublicKey, gossips: I) -> Vec<Arc<Profile>>
+    where
+        I: IntoIterator<Item = Gossip>,
+    {
+        let mut learned = HashSet::new();
+        let mut sender_gossip = false;
+
+        for gossip in gossips {
+            let id = gossip.id();
+            match self.import_gossip_from(from, gossip) {
+                ImportOutcome::New | ImportOutcome::Updated | ImportOutcome::Unchanged
+                    if &id == from =>
+                {
+                    sender_gossip = true;
+                }
+                ImportOutcome::New => {
+                    learned.insert(id);
+                }
+                _ => (),
+            }
+        }
+
+        if sender_gossip && !self.is_banned(from) {
+            self.promote_peer(from);
+        }
+
+        if learned.is_empty() {
+            return Vec::new();
+        }
+
+        let view = self.view(None, Selection::Any);
+        view.into_iter()
+            .filter(|profile| learned.contains(&profile.id()))
+            .filter(|profile| {
+                profile
+                    .gossip()
+                    .subscriptions()
+                    .iter_topics()
+                    .any(|topic| self.is_subscribed(&topic))
+            })
+            .collect()
     }
 
     pub fn gossips_for(&mut self, recipient: &ed25519::PublicKey) -> Vec<Gossip> {
-        let mut gossips = Vec::with_capacity(1024);
+        self.gossips_for_at(recipient, Time::now())
+    }
+
+    fn gossips_for_at(&mut self, recipient: &ed25519::PublicKey, now: Time) -> Vec<Gossip> {
+        let recipient = if let Some(recipient) = self.profiles.get(recipient) {
+            Arc::clone(recipient)
+        } else {
+            return Vec::new();
+        };
+
+        let view = self.view(None, Selection::Any);
+
+        self.select_gossips(&recipient, &view, now)
+    }
 
+    /// do not gossip about the peers whose last gossip is older than
+    /// `max_age`: they are likely offline
+    ///
+    /// this applies to [`Topology::gossips_for`],
+    /// [`Topology::gossips_for_many`] and [`Topology::estimate_gossip_size`].
+    /// Our own gossip is always sent. There is no maximum age by default.
+    pub fn set_gossip_max_age(&mut self, max_age: Duration) {
+        self.gossip_max_age = Some(max_age);
+    }
+
+    /// size in bytes of the gossips [`Topology::gossips_for`] would return
+    /// for the recipient, without copying the gossips
+    pub fn estimate_gossip_size(&mut self, recipient: &ed25519::PublicKey) -> usize {
         let recipient = if let Some(recipient) = self.profiles.get(recipient) {
             Arc::clone(recipient)
         } else {
-            return gossips;
+            return 0;
         };
 
+        let view = self.view(None, Selection::Any);
+
+        self.select_gossip_profiles(&recipient, &view, Time::now())
+            .iter()
+            .map(|profile| profile.gossip())
+            .chain(std::iter::once(self.profile.gossip()))
+            .map(|gossip| gossip.as_ref().len())
+            .sum()
+    }
+
+    /// gossips of the peers of our view the requester does not know about
+    ///
+    /// This is for pull based anti-entropy: the requester sends the list of
+    /// the peers it already knows and we reply with the missing ones. Our
+    /// own gossip is included unless the requester already knows us.
+    pub fn gossips_requested_by(
+        &mut self,
+        requester: &ed25519::PublicKey,
+        known_to_requester: &HashSet<ed25519::PublicKey>,
+    ) -> Vec<Gossip> {
+        let view = self.view(Some(requester), Selection::Any);
+
+        view.iter()
+            .map(|profile| profile.gossip())
+            .chain(std::iter::once(self.profile.gossip()))
+            .filter(|gossip| {
+                let id = gossip.id();
+                &id != requester && !known_to_requester.contains(&id)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// same as [`Topology::gossips_for`] but for multiple recipients at once
+    ///
+    /// the view the gossips are selected from is computed only once for all
+    /// the recipients. The gossip layers still need to be populated for every
+    /// recipient as the selection is relative to the recipient (its topics,
+    /// its proximity with the other peers...).
+    ///
+    /// Unknown recipients are associated to an empty list of gossips.
+    pub fn gossips_for_many(
+        &mut self,
+        recipients: &[ed25519::PublicKey],
+    ) -> HashMap<ed25519::PublicKey, Vec<Gossip>> {
+        let mut gossips = HashMap::with_capacity(recipients.len());
+        let mut view = None;
+        let now = Time::now();
+
+        for id in recipients {
+            let recipient = if let Some(recipient) = self.profiles.get(id) {
+                Arc::clone(recipient)
+            } else {
+                gossips.insert(*id, Vec::new());
+                continue;
+            };
+
+            let view = view.get_or_insert_with(|| self.view(None, Selection::Any));
+            let recipient_gossips = self.select_gossips(&recipient, view, now);
+            gossips.insert(*id, recipient_gossips);
+        }
+
+        gossips
+    }
+
+    fn select_gossips(
+        &mut self,
+        recipient: &Profile,
+        view: &[Arc<Profile>],
+        now: Time,
+    ) -> Vec<Gossip> {
+        let selected = self.select_gossip_profiles(recipient, view, now);
+
+        let mut gossips = Vec::with_capacity(selected.len() + 1);
+        gossips.extend(selected.iter().map(|profile| profile.gossip().clone()));
+        gossips.push(self.profile.gossip().clone());
+
+        gossips
+    }
+
+    /// the profiles whose gossips are to be sent to the recipient, our
+    /// own profile excepted
+    ///
+    /// Our own profile may be in the pools if a peer echoed our gossip
+    /// back to us: it is filtered out so our gossip (which is always added
+    /// by the callers) is never sent twice. The profiles older than the
+    /// maximum age (see [`Topology::set_gossip_max_age`]) are filtered out.
+    fn select_gossip_profiles(
+        &mut self,
+        recipient: &Profile,
+        view: &[Arc<Profile>],
+        now: Time,
+    ) -> Vec<Arc<Profile>> {
+        let mut selected = self.select_gossip_profiles_unfiltered(recipient, view);
+
+        if let Some(max_age) = self.gossip_max_age {
+            let now = now.seconds_since_covid_epoch();
+            selected.retain(|profile| {
+                let age = now.saturating_sub(profile.last_update().seconds_since_covid_epoch());
+                u64::from(age) <= max_age.as_secs()
+            });
+        }
+
+        selected
+    }
+
+    fn select_gossip_profiles_unfiltered(
+        &mut self,
+        recipient: &Profile,
+        view: &[Arc<Profile>],
+    ) -> Vec<Arc<Profile>> {
         let id = recipient.id();
+        let ours = self.profile.id();
+
+        if self.is_small_pool() {
+            // the view is already all the peers we know about
+            return view
+                .iter()
+                .filter(|profile| profile.id() != id && profile.id() != ours)
+                .cloned()
+                .collect();
+        }
 
         for layer in self.gossip_layers.iter_mut() {
             layer.reset();
         }
 
-        for subscription in recipient.subscriptions().iter() {
+        for topic in recipient.subscriptions().iter_topics() {
+            for layer in self.gossip_layers.iter_mut() {
+                layer.subscribe(topic);
+            }
+        }
+
+        for profile in view {
             for layer in self.gossip_layers.iter_mut() {
-                layer.subscribe(subscription.topic());
+                layer.populate(recipient, profile);
             }
         }
 
-        for profile in self.view(None, Selection::Any) {
+        // our view is built from our interests, we may know about peers
+        // that are relevant to the recipient's topics but that are not
+        // part of our view.
+        let topics: HashSet<Topic> = recipient.gossip().subscriptions().iter_topics().collect();
+        let in_view: HashSet<ed25519::PublicKey> =
+            view.iter().map(|profile| profile.id()).collect();
+        let subscribers = self
+            .profiles
+            .trusted()
+            .iter()
+            .chain(self.profiles.pool().iter())
+            .map(|(_, profile)| profile)
+            .filter(|profile| !in_view.contains(&profile.id()))
+            .filter(|profile| {
+                profile
+                    .gossip()
+                    .subscriptions()
+                    .iter_topics()
+                    .any(|topic| topics.contains(&topic))
+            });
+        for profile in subscribers {
             for layer in self.gossip_layers.iter_mut() {
-                layer.populate(recipient.as_ref(), &profile);
+                layer.populate(recipient, profile);
             }
         }
 
@@ -160,13 +902,14 @@ impl Topology {
         for layer in self.gossip_layers.iter_mut() {
             layer.view(&mut builder);
         }
-        let mut keys = builder.build();
-
-        keys.remove(&id); // remove the recipient's ID
+        // the gossips are selected in the order the layers picked them so
+        // the same topology state always yields the same gossips
+        let keys = builder.build_ordered();
+        let mut selected = Vec::with_capacity(keys.len());
 
-        for key in keys {
+        for key in keys.into_iter().filter(|key| key != &id && key != &ours) {
             if let Some(profile) = self.profiles.get(&key) {
-                gossips.push(profile.gossip().clone());
+                selected.push(Arc::clone(profile));
             } else {
                 // we populated the gossip's view with the profiles' nodes
                 // so we should have all the entries that have been selected
@@ -175,47 +918,2050 @@ impl Topology {
             }
         }
 
-        gossips.push(self.profile.gossip().clone());
+        selected
+    }
 
-        gossips
+    /// set the number of peers under which [`Topology::view`] and
+    /// [`Topology::gossips_for`] skip the layers and simply
+    /// select all the peers we know (except the ones in the dirty pool)
+    ///
+    /// This is useful while joining the network: with only a handful of
+    /// known peers we want all of them in the view. Set to `0` (the default)
+    /// to always use the layers.
+    pub fn set_small_pool_threshold(&mut self, threshold: usize) {
+        self.invalidate_view_cache();
+
+        self.small_pool_threshold = threshold;
+    }
+
+    fn is_small_pool(&self) -> bool {
+        let len = self.profiles.trusted().len() + self.profiles.pool().len();
+        len > 0 && len <= self.small_pool_threshold
     }
 
+    /// compute the view for the given selection
+    ///
+    /// The contributions of the stable layers (see [`Layer::is_stable`])
+    /// are cached until the topology is modified (peers added or removed,
+    /// topics subscribed or unsubscribed). The other layers, like the
+    /// rings which rotate their neighbors, are run on every call. At most
+    /// [`Topology::MAX_CACHED_VIEWS`] views are cached. See
+    /// [`Topology::invalidate_view_cache`].
     pub fn view(
         &mut self,
         from: Option<&ed25519::PublicKey>,
         selection: Selection,
     ) -> Vec<Arc<Profile>> {
-        let mut builder = ViewBuilder::new(selection);
-        if let Some(origin) = from {
-            builder.with_origin(*origin);
-        }
+        let mut view = self.compute_view(from, selection);
 
-        for layer in self.view_layers.iter_mut() {
-            layer.view(&mut builder);
+        if self.is_self_selected(selection) {
+            view.push(self.own_view_profile());
         }
 
-        let keys = builder.build();
+        view
+    }
 
-        let mut profiles = Vec::with_capacity(keys.len());
+    /// add our own profile at the end of [`Topology::view`] (if we are
+    /// subscribed to the selected topic). Disabled by default.
+    ///
+    /// This also applies to [`Topology::view_size`],
+    /// [`Topology::view_addresses`] and [`Topology::detailed_view`]. This
+    /// is meant for tests and single node deployments where the view would
+    /// otherwise be empty.
+    pub fn include_self(&mut self, include: bool) {
+        self.include_self = include;
+    }
 
-        for key in keys {
-            if let Some(profile) = self.profiles.get(&key) {
-                profiles.push(Arc::clone(profile));
+    /// check if our own profile is to be added to the view of the
+    /// selection, see [`Topology::include_self`]
+    fn is_self_selected(&self, selection: Selection) -> bool {
+        self.include_self
+            && match selection {
+                Selection::Any => true,
+                Selection::Topic { topic } => self.is_subscribed(&topic),
+            }
+    }
+
+    fn own_view_profile(&self) -> Arc<Profile> {
+        Arc::new(Profile::from_gossip(self.profile.gossip().clone()))
+    }
+
+    /// same as [`Topology::view`] but if the selection yields less than
+    /// `min` profiles, the view is topped up with the profiles of the
+    /// `fallback` selection
+    ///
+    /// Typically used with [`Selection::Any`] as fallback so an event for
+    /// a topic we do not know any subscriber of still has somewhere to go.
+    pub fn view_with_fallback(
+        &mut self,
+        selection: Selection,
+        fallback: Selection,
+        min: usize,
+    ) -> Vec<Arc<Profile>> {
+        let mut view = self.view(None, selection);
+        if view.len() >= min {
+            return view;
+        }
+
+        let mut selected: HashSet<ed25519::PublicKey> =
+            view.iter().map(|profile| profile.id()).collect();
+        for profile in self.view(None, fallback) {
+            if view.len() >= min {
+                break;
+            }
+            if selected.insert(profile.id()) {
+                view.push(profile);
             }
         }
 
-        profiles
+        view
     }
 
-    pub fn get(&mut self, id: &ed25519::PublicKey) -> Option<&Arc<Profile>> {
-        self.profiles.get(id)
+    /// same as [`Topology::view`] but keeps at most `max_per_subnet`
+    /// profiles of a same subnet (`/24` for IPv4, `/48` for IPv6)
+    ///
+    /// Spreading the peers we connect to across subnets makes it harder
+    /// for a single operator to surround our node.
+    pub fn diverse_view(
+        &mut self,
+        selection: Selection,
+        max_per_subnet: usize,
+    ) -> Vec<Arc<Profile>> {
+        let mut per_subnet: HashMap<(bool, [u8; 6]), usize> = HashMap::new();
+
+        self.view(None, selection)
+            .into_iter()
+            .filter(|profile| {
+                let count = per_subnet.entry(subnet(profile.address())).or_insert(0);
+                *count += 1;
+                *count <= max_per_subnet
+            })
+            .collect()
     }
 
-    pub fn peers(&self) -> &Profiles {
-        &self.profiles
+    /// the addresses of the profiles of [`Topology::view`], without
+    /// duplicates and in the same order (unless an address preference
+    /// is set, see [`Topology::set_address_preference`])
+    pub fn view_addresses(&mut self, selection: Selection) -> Vec<SocketAddr> {
+        let view = self.view(None, selection);
+        let mut seen = HashSet::with_capacity(view.len());
+        let mut addresses: Vec<SocketAddr> = view
+            .iter()
+            .map(|profile| profile.address())
+            .filter(|address| seen.insert(*address))
+            .collect();
+
+        match self.address_preference {
+            AddressPreference::NoPreference => (),
+            AddressPreference::Ipv4First => addresses.sort_by_key(|address| address.is_ipv6()),
+            AddressPreference::Ipv6First => addresses.sort_by_key(|address| address.is_ipv4()),
+        }
+
+        addresses
     }
 
-    pub fn self_profile(&self) -> &Profile {
-        &self.profile
+    /// set which IP family [`Topology::view_addresses`] lists first
+    ///
+    /// every peer advertises only one address: this only changes the
+    /// order of the addresses, the view itself is the same
+    pub fn set_address_preference(&mut self, preference: AddressPreference) {
+        self.address_preference = preference;
+    }
+
+    /// number of profiles [`Topology::view`] returns for the selection,
+    /// without collecting the profiles
+    pub fn view_size(&mut self, selection: Selection) -> usize {
+        let keys = self.view_keys(None, selection);
+
+        let profiles = &self.profiles;
+        let size = keys
+            .iter()
+            .filter(|(key, _)| profiles.peek(key).is_some())
+            .count();
+
+        if self.is_self_selected(selection) {
+            size + 1
+        } else {
+            size
+        }
+    }
+
+    /// same as [`Topology::view`] but the profiles are sorted with the
+    /// given ordering
+    pub fn view_ordered(
+        &mut self,
+        selection: Selection,
+        ordering: ViewOrdering,
+    ) -> Vec<Arc<Profile>> {
+        let mut view = self.view(None, selection);
+
+        match ordering {
+            ViewOrdering::LayerPriority => {}
+            ViewOrdering::FreshnessDesc => {
+                view.sort_by_key(|profile| Reverse(profile.last_update()));
+            }
+            ViewOrdering::ProximityDesc => {
+                let ours = &self.profile;
+                view.sort_by_key(|profile| Reverse(ours.proximity_to(profile)));
+            }
+        }
+
+        view
+    }
+
+    /// drop the cached views, the next call to [`Topology::view`]
+    /// will run the layers again
+    pub fn invalidate_view_cache(&mut self) {
+        self.view_cache.clear();
+    }
+
+    /// drop the cached contributions of the layers that may be affected
+    /// by a change on the given topics
+    ///
+    /// The topic scoped layers (see [`Layer::is_topic_scoped`]) keep their
+    /// contributions to the views of the other topics, the contributions of
+    /// the other layers are all dropped. With the default layers only the
+    /// rings are topic scoped and they are never cached (see
+    /// [`Layer::is_stable`]): every cached view is dropped.
+    fn invalidate_view_cache_for(&mut self, topics: &HashSet<Topic>) {
+        let topic_scoped: Vec<bool> = self
+            .view_layers
+            .iter()
+            .map(|layer| layer.is_topic_scoped())
+            .collect();
+
+        let mut emptied = Vec::new();
+        for (key, cached) in self.view_cache.iter_mut() {
+            let unaffected = match key.1 {
+                Selection::Any => false,
+                Selection::Topic { topic } => !topics.contains(&topic),
+            };
+
+            for (cached, topic_scoped) in cached.iter_mut().zip(topic_scoped.iter()) {
+                if !(unaffected && *topic_scoped) {
+                    *cached = None;
+                }
+            }
+
+            if cached.iter().all(Option::is_none) {
+                emptied.push(*key);
+            }
+        }
+        for key in emptied {
+            self.view_cache.pop(&key);
+        }
+    }
+
+    /// the nodes of the view (see [`Topology::view`]) and why they have
+    /// been selected
+    fn view_keys(
+        &mut self,
+        from: Option<&ed25519::PublicKey>,
+        selection: Selection,
+    ) -> Vec<(ed25519::PublicKey, Selection)> {
+        if self.is_small_pool() {
+            return self
+                .profiles
+                .trusted()
+                .iter()
+                .chain(self.profiles.pool().iter())
+                .map(|(id, _)| (*id, Selection::Any))
+                .collect();
+        }
+
+        let new_builder = || {
+            let mut builder = ViewBuilder::new(selection);
+            if let Some(origin) = from {
+                builder.with_origin(*origin);
+            }
+            builder
+        };
+
+        let key = (from.copied(), selection);
+        let mut cached = self
+            .view_cache
+            .pop(&key)
+            .unwrap_or_else(|| vec![None; self.view_layers.len()]);
+
+        let mut builder = new_builder();
+        for (layer, cached) in self.view_layers.iter_mut().zip(cached.iter_mut()) {
+            if cached.is_none() && layer.is_stable() {
+                let mut own = new_builder();
+                layer.view(&mut own);
+                *cached = Some(own.build_detailed());
+            }
+
+            if let Some(nodes) = cached {
+                for (node, via) in nodes.iter() {
+                    builder.add_via(node, *via);
+                }
+            } else {
+                layer.view(&mut builder);
+            }
+        }
+        if cached.iter().any(Option::is_some) {
+            self.view_cache.put(key, cached);
+        }
+
+        builder.build_detailed()
+    }
+
+    fn compute_view(
+        &mut self,
+        from: Option<&ed25519::PublicKey>,
+        selection: Selection,
+    ) -> Vec<Arc<Profile>> {
+        let keys = self.view_keys(from, selection);
+
+        let mut profiles = Vec::with_capacity(keys.len());
+
+        for (key, _) in keys {
+            if let Some(profile) = self.profiles.get(&key) {
+                profiles.push(Arc::clone(profile));
+            }
+        }
+
+        profiles
+    }
+
+    /// list all the topics the known peers are subscribed to
+    ///
+    /// the peers of the dirty pool (see [`Topology::remove_peer`]) are
+    /// not taken into account
+    pub fn known_topics(&self) -> BTreeSet<Topic> {
+        self.profiles
+            .trusted()
+            .iter()
+            .chain(self.profiles.pool().iter())
+            .flat_map(|(_, profile)| profile.gossip().subscriptions().iter_topics())
+            .collect()
+    }
+
+    /// check if we are subscribed to the topic (see [`Topology::subscribe_topic`])
+    pub fn is_subscribed(&self, topic: &Topic) -> bool {
+        self.subscribed_topics.contains(topic)
+    }
+
+    /// the topics we are not subscribed to but that have at least
+    /// `min_subscribers` known subscribers, sorted
+    ///
+    /// the subscribers are counted with [`Topology::topic_subscriber_counts`]
+    /// so the peers of the dirty pool are not taken into account
+    pub fn suggested_topics(&self, min_subscribers: usize) -> Vec<Topic> {
+        self.topic_subscriber_counts()
+            .into_iter()
+            .filter(|(topic, count)| *count >= min_subscribers && !self.is_subscribed(topic))
+            .map(|(topic, _)| topic)
+            .collect()
+    }
+
+    /// number of known peers subscribed to each topic
+    ///
+    /// the peers of the dirty pool are not counted, see
+    /// [`Topology::known_topics`]
+    pub fn topic_subscriber_counts(&self) -> BTreeMap<Topic, usize> {
+        let mut counts = BTreeMap::new();
+        let profiles = self
+            .profiles
+            .trusted()
+            .iter()
+            .chain(self.profiles.pool().iter());
+        for (_, profile) in profiles {
+            profile
+                .gossip()
+                .subscriptions()
+                .for_each_topic(|topic| *counts.entry(topic).or_insert(0) += 1);
+        }
+        counts
+    }
+
+    /// our neighbors in the rings of the given topic
+    ///
+    /// unlike [`Topology::view`] this does not flatten the ring: the
+    /// neighbors are split on either side of our id. This is empty if
+    /// we are not subscribed to the topic or if no view layer maintains
+    /// rings.
+    pub fn ring_neighbors(&self, topic: &Topic) -> RingNeighbors {
+        let ours = self.profile.id();
+        let mut neighbors = BTreeSet::new();
+
+        for layer in self.view_layers.iter() {
+            if let layer::LayerState::Rings(links) = layer.snapshot() {
+                for (_, predecessors, successors) in links.into_iter().filter(|l| &l.0 == topic) {
+                    neighbors.extend(predecessors);
+                    neighbors.extend(successors);
+                }
+            }
+        }
+
+        let (predecessors, successors) = neighbors
+            .into_iter()
+            .filter(|id| id != &ours)
+            .partition(|id| id < &ours);
+
+        RingNeighbors {
+            predecessors,
+            successors,
+        }
+    }
+
+    /// run every view layer on its own for the given selection and count
+    /// the peers selected by more than one layer
+    ///
+    /// a high overlap means the layers are redundant and the view is
+    /// smaller than the sum of the layers' sizes.
+    pub fn layer_overlap(&mut self, selection: Selection) -> OverlapReport {
+        let contributions: Vec<(&'static str, HashSet<ed25519::PublicKey>)> = self
+            .view_layers
+            .iter_mut()
+            .map(|layer| {
+                let mut builder = ViewBuilder::new(selection);
+                layer.view(&mut builder);
+                (layer.name(), builder.build_ordered().into_iter().collect())
+            })
+            .collect();
+
+        let mut report = OverlapReport {
+            layers: contributions
+                .iter()
+                .map(|(name, nodes)| (*name, nodes.len()))
+                .collect(),
+            overlaps: Vec::new(),
+        };
+
+        for (index, (first, first_nodes)) in contributions.iter().enumerate() {
+            for (second, second_nodes) in contributions.iter().skip(index + 1) {
+                let count = first_nodes.intersection(second_nodes).count();
+                report.overlaps.push((*first, *second, count));
+            }
+        }
+
+        report
+    }
+
+    /// same as [`Topology::view`] but also tells why each profile
+    /// has been selected
+    pub fn detailed_view(&mut self, selection: Selection) -> Vec<ViewEntry> {
+        let keys = self.view_keys(None, selection);
+
+        let mut entries = Vec::with_capacity(keys.len());
+
+        for (key, via) in keys {
+            if let Some(profile) = self.profiles.get(&key) {
+                entries.push(ViewEntry {
+                    profile: Arc::clone(profile),
+                    via,
+                });
+            }
+        }
+
+        if self.is_self_selected(selection) {
+            entries.push(ViewEntry {
+                profile: self.own_view_profile(),
+                via: selection,
+            });
+        }
+
+        entries
+    }
+
+    /// check the consistency of the internal state
+    ///
+    /// every peer referenced by the view layers must be in the profile
+    /// pools, a peer must not be in more than one pool and our own profile
+    /// must not be in any of them. This is meant for debugging custom
+    /// layers or restored states (see [`Layer::restore`]). The gossip
+    /// layers are not checked: they are rebuilt every time they are used.
+    pub fn validate(&self) -> Result<(), Vec<ConsistencyError>> {
+        let mut errors = Vec::new();
+
+        for layer in self.view_layers.iter() {
+            let mut nodes = layer.snapshot().nodes();
+            nodes.sort();
+            nodes.dedup();
+            for id in nodes {
+                if self.profiles.peek(&id).is_none() {
+                    errors.push(ConsistencyError::DanglingLayerKey {
+                        layer: layer.name(),
+                        id,
+                    });
+                }
+            }
+        }
+
+        let mut seen = HashSet::with_capacity(self.profiles.len());
+        let mut duplicates = BTreeSet::new();
+        for profile in self.profiles.iter() {
+            let id = profile.id();
+            if !seen.insert(id) {
+                duplicates.insert(id);
+            }
+        }
+        errors.extend(
+            duplicates
+                .into_iter()
+                .map(|id| ConsistencyError::MultiplePools { id }),
+        );
+
+        if seen.contains(&self.profile.id()) {
+            errors.push(ConsistencyError::OwnProfileInPool);
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// number of peers we know about, in all the profile pools
+    pub fn peer_count(&self) -> usize {
+        self.profiles.len()
+    }
+
+    /// check if we know the given peer
+    ///
+    /// unlike [`Topology::get`] this does not update the order in which the
+    /// peers will be evicted from the profile pools.
+    pub fn contains_peer(&self, id: &ed25519::PublicKey) -> bool {
+        self.profiles.peek(id).is_some()
+    }
+
+    pub fn get(&mut self, id: &ed25519::PublicKey) -> Option<&Arc<Profile>> {
+        self.profiles.get(id)
+    }
+
+    /// same as [`Topology::get`] but the profile can be kept while the
+    /// topology is modified. It is not updated with the newer gossips.
+    pub fn peer_profile(&mut self, id: &ed25519::PublicKey) -> Option<Arc<Profile>> {
+        self.profiles.get(id).cloned()
+    }
+
+    pub fn peers(&self) -> &Profiles {
+        &self.profiles
+    }
+
+    /// iterate through all the known peers
+    ///
+    /// this does not require a mutable reference to the `Topology` and
+    /// does not update the order in which the peers are evicted.
+    pub fn iter_peers(&self) -> impl Iterator<Item = &'_ Arc<Profile>> {
+        self.profiles.iter()
+    }
+
+    pub fn self_profile(&self) -> &Profile {
+        &self.profile
+    }
+
+    /// our own gossip, as it is shared with the other peers
+    pub fn self_gossip(&self) -> &Gossip {
+        self.profile.gossip()
+    }
+
+    /// the address we advertise to the other peers
+    pub fn local_address(&self) -> SocketAddr {
+        self.profile.address()
+    }
+
+    /// change the address we advertise to the other peers
+    ///
+    /// use this if our public address changed (new IP address, different
+    /// port...). Our gossip is rebuilt and signed with the given key.
+    pub fn update_local_address(&mut self, address: SocketAddr, id: &ed25519::SecretKey) {
+        self.profile.commit_gossip_with_address(address, id);
+    }
+}
+
+/// the `/24` (IPv4) or `/48` (IPv6) prefix of the address
+fn subnet(address: SocketAddr) -> (bool, [u8; 6]) {
+    let mut prefix = [0; 6];
+    match address.ip() {
+        IpAddr::V4(ip) => {
+            prefix[..3].copy_from_slice(&ip.octets()[..3]);
+            (true, prefix)
+        }
+        IpAddr::V6(ip) => {
+            prefix.copy_from_slice(&ip.octets()[..6]);
+            (false, prefix)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{InterestLevel, Subscription, Subscriptions};
+    use keynesis::Seed;
+
+    fn secret_key(seed: u8) -> ed25519::SecretKey {
+        let rng = Seed::from([seed; Seed::SIZE]).into_rand_chacha();
+        ed25519::SecretKey::new(rng)
+    }
+
+    fn topic(byte: u8) -> Topic {
+        Topic::new([byte; Topic::SIZE])
+    }
+
+    fn gossip_with(id: &ed25519::SecretKey, time: u32, topics: &[Topic]) -> Gossip {
+        let address: SocketAddr = "127.0.0.1:9876".parse().unwrap();
+        let mut subscriptions = Subscriptions::new();
+        for topic in topics {
+            let subscription = Subscription::new(*topic, InterestLevel::new(1));
+            subscriptions.push(subscription.as_slice()).unwrap();
+        }
+        Gossip::new_at(address, id, subscriptions.as_slice(), Time::from(time))
+    }
+
+    fn gossip_at(id: &ed25519::SecretKey, time: u32) -> Gossip {
+        gossip_with(id, time, &[])
+    }
+
+    struct RingsOnly;
+
+    impl LayerBuilder for RingsOnly {
+        fn build_for_view(&self) -> Vec<Box<dyn Layer>> {
+            vec![Box::new(layer::Rings::new(4))]
+        }
+
+        fn build_for_gossip(&self) -> Vec<Box<dyn Layer>> {
+            vec![Box::new(layer::Rings::new(4))]
+        }
+    }
+
+    fn topology() -> Topology {
+        let address: SocketAddr = "127.0.0.1:1234".parse().unwrap();
+        Topology::new(address, &secret_key(0))
+    }
+
+    #[test]
+    fn import_gossip_outcomes() {
+        let mut topology = topology();
+        let peer = secret_key(1);
+
+        assert!(matches!(
+            topology.import_gossip(gossip_at(&peer, 10)),
+            ImportOutcome::New
+        ));
+        assert!(matches!(
+            topology.import_gossip(gossip_at(&peer, 20)),
+            ImportOutcome::Updated
+        ));
+        assert!(matches!(
+            topology.import_gossip(gossip_at(&peer, 15)),
+            ImportOutcome::Unchanged
+        ));
+        assert_eq!(
+            topology.get(&peer.public_key()).unwrap().last_update(),
+            Time::from(20)
+        );
+    }
+
+    #[test]
+    fn import_gossip_dirty_and_own() {
+        let mut topology = topology();
+        let peer = secret_key(1);
+
+        topology.import_gossip(gossip_at(&peer, 10));
+        topology.remove_peer(&peer.public_key());
+        assert!(topology.profiles.dirty().contains(&peer.public_key()));
+
+        // the peer stays in the dirty pool, out of the layers
+        assert!(matches!(
+            topology.import_gossip(gossip_at(&peer, 20)),
+            ImportOutcome::Unchanged
+        ));
+        assert!(topology.view(None, Selection::Any).is_empty());
+
+        let ours = secret_key(0);
+        assert!(matches!(
+            topology.import_gossip(gossip_at(&ours, u32::MAX)),
+            ImportOutcome::Unchanged
+        ));
+        assert!(topology.get(&ours.public_key()).is_none());
+    }
+
+    #[test]
+    fn import_gossip_rejects_tampered() {
+        let mut topology = topology();
+        let peer = secret_key(1);
+
+        let mut bytes = gossip_at(&peer, 10).as_ref().to_vec();
+        // change the port so the signature does not match anymore
+        let port_index = bytes.len() - ed25519::Signature::SIZE - 1;
+        bytes[port_index] ^= 0xFF;
+        let tampered = GossipSlice::from_slice_unchecked(&bytes).to_owned();
+
+        assert!(matches!(
+            topology.import_gossip(tampered),
+            ImportOutcome::Rejected(GossipError::InvalidSignature)
+        ));
+        assert!(topology.get(&peer.public_key()).is_none());
+    }
+
+    #[test]
+    fn known_topics_union() {
+        let mut topology = topology();
+
+        topology.import_gossip(gossip_with(&secret_key(1), 1, &[topic(1), topic(2)]));
+        topology.import_gossip(gossip_with(&secret_key(2), 1, &[topic(2), topic(3)]));
+        topology.import_gossip(gossip_with(&secret_key(3), 1, &[]));
+
+        let expected: BTreeSet<Topic> = vec![topic(1), topic(2), topic(3)].into_iter().collect();
+        assert_eq!(topology.known_topics(), expected);
+
+        topology.import_gossip(gossip_with(&secret_key(4), 1, &[topic(4)]));
+        topology.remove_peer(&secret_key(4).public_key());
+        assert_eq!(topology.known_topics(), expected);
+    }
+
+    #[test]
+    fn detailed_view_via() {
+        let mut topology = topology();
+        topology.subscribe_topic(topic(1)).unwrap();
+
+        let neighbor = secret_key(1).public_key();
+        let random = secret_key(2).public_key();
+        topology.import_gossip(gossip_with(&secret_key(1), 1, &[topic(1)]));
+        topology.import_gossip(gossip_with(&secret_key(2), 1, &[]));
+
+        let entries = topology.detailed_view(Selection::Any);
+        assert_eq!(entries.len(), 2);
+
+        for entry in entries {
+            if entry.profile.id() == neighbor {
+                assert_eq!(entry.via, Selection::Topic { topic: topic(1) });
+            } else {
+                assert_eq!(entry.profile.id(), random);
+                assert_eq!(entry.via, Selection::Any);
+            }
+        }
+    }
+
+    #[test]
+    fn subscribe_topic_seeds_rings() {
+        let address: SocketAddr = "127.0.0.1:1234".parse().unwrap();
+        let mut topology = Topology::new_with(address, &secret_key(0), RingsOnly);
+
+        topology.import_gossip(gossip_with(&secret_key(1), 1, &[topic(1)]));
+        topology.import_gossip(gossip_with(&secret_key(2), 1, &[topic(1)]));
+        topology.import_gossip(gossip_with(&secret_key(3), 1, &[topic(2)]));
+
+        let selection = Selection::Topic { topic: topic(1) };
+        assert!(topology.view(None, selection).is_empty());
+
+        topology.subscribe_topic(topic(1)).unwrap();
+
+        let view = topology.view(None, selection);
+        assert!(!view.is_empty());
+        assert!(view
+            .iter()
+            .all(|profile| profile.id() != secret_key(3).public_key()));
+    }
+
+    #[test]
+    fn subscribe_topic_skips_dirty_peers() {
+        let mut topology = topology();
+        let demoted = secret_key(1).public_key();
+
+        topology.import_gossip(gossip_with(&secret_key(1), 1, &[topic(1)]));
+        topology.import_gossip(gossip_with(&secret_key(2), 1, &[topic(1)]));
+        topology.remove_peer(&demoted);
+        assert!(topology.profiles.dirty().contains(&demoted));
+
+        topology.subscribe_topic(topic(1)).unwrap();
+
+        for selection in vec![Selection::Any, Selection::Topic { topic: topic(1) }] {
+            let view = topology.view(None, selection);
+            assert!(view
+                .iter()
+                .any(|profile| profile.id() == secret_key(2).public_key()));
+            assert!(view.iter().all(|profile| profile.id() != demoted));
+        }
+    }
+
+    #[test]
+    fn gossips_for_many_matches_gossips_for() {
+        let mut topology = topology();
+        topology.subscribe_topic(topic(1)).unwrap();
+
+        for seed in 1..6 {
+            let topics = if seed % 2 == 0 {
+                vec![topic(1)]
+            } else {
+                vec![topic(2)]
+            };
+            topology.import_gossip(gossip_with(&secret_key(seed), 1, &topics));
+        }
+
+        let ids = |gossips: &[Gossip]| -> BTreeSet<ed25519::PublicKey> {
+            gossips.iter().map(|gossip| gossip.id()).collect()
+        };
+
+        let recipients = vec![
+            secret_key(1).public_key(),
+            secret_key(2).public_key(),
+            secret_key(42).public_key(),
+        ];
+        let many = topology.gossips_for_many(&recipients);
+        assert_eq!(many.len(), recipients.len());
+
+        for recipient in recipients {
+            let expected = topology.gossips_for(&recipient);
+            assert_eq!(ids(&many[&recipient]), ids(&expected));
+        }
+    }
+
+    #[test]
+    fn contains_peer() {
+        let mut topology = topology();
+        let first = secret_key(1).public_key();
+        let second = secret_key(2).public_key();
+
+        assert!(!topology.contains_peer(&first));
+
+        topology.import_gossip(gossip_at(&secret_key(1), 1));
+        topology.import_gossip(gossip_at(&secret_key(2), 1));
+
+        assert!(topology.contains_peer(&first));
+        assert_eq!(topology.peers().pool().peek_lru().unwrap().0, &first);
+
+        topology.forget_peer(&first);
+        assert!(!topology.contains_peer(&first));
+        assert!(topology.contains_peer(&second));
+    }
+
+    #[test]
+    fn handle_disconnect() {
+        let mut topology = topology();
+        let peer = secret_key(1).public_key();
+        topology.import_gossip(gossip_at(&secret_key(1), 1));
+        topology.promote_peer(&peer);
+        assert!(topology.peers().trusted().contains(&peer));
+
+        topology.handle_disconnect(&peer, true);
+        assert!(topology.peers().trusted().contains(&peer));
+        assert!(topology
+            .view(None, Selection::Any)
+            .iter()
+            .all(|profile| profile.id() != peer));
+
+        topology.handle_disconnect(&peer, false);
+        assert!(topology.peers().pool().contains(&peer));
+        topology.handle_disconnect(&peer, false);
+        assert!(topology.peers().dirty().contains(&peer));
+
+        // a peer in the dirty pool is not considered anymore
+        assert!(!topology.add_peer(Profile::from_gossip(gossip_at(&secret_key(1), 2))));
+    }
+
+    #[test]
+    fn small_pool_view() {
+        let address: SocketAddr = "127.0.0.1:1234".parse().unwrap();
+        let mut topology = Topology::new_with(address, &secret_key(0), RingsOnly);
+        topology.subscribe_topic(topic(1)).unwrap();
+
+        for seed in 1..4 {
+            topology.import_gossip(gossip_with(&secret_key(seed), 1, &[topic(2)]));
+        }
+
+        let selection = Selection::Topic { topic: topic(1) };
+        assert!(topology.view(None, selection).is_empty());
+
+        topology.set_small_pool_threshold(3);
+        assert_eq!(topology.view(None, selection).len(), 3);
+        // all the peers but the recipient, and our own gossip
+        assert_eq!(topology.gossips_for(&secret_key(1).public_key()).len(), 3);
+
+        topology.set_small_pool_threshold(2);
+        assert!(topology.view(None, selection).is_empty());
+    }
+
+    #[test]
+    fn view_cache() {
+        let mut topology = topology();
+        topology.import_gossip(gossip_at(&secret_key(1), 1));
+        topology.import_gossip(gossip_at(&secret_key(2), 1));
+
+        let ids = |view: Vec<Arc<Profile>>| -> Vec<ed25519::PublicKey> {
+            view.iter().map(|profile| profile.id()).collect()
+        };
+
+        let first = topology.view(None, Selection::Any);
+        let second = topology.view(None, Selection::Any);
+        assert_eq!(first.len(), 2);
+        assert!(first
+            .iter()
+            .zip(second.iter())
+            .all(|(a, b)| Arc::ptr_eq(a, b)));
+        assert_eq!(ids(first), ids(second));
+
+        topology.import_gossip(gossip_at(&secret_key(3), 1));
+        let third = topology.view(None, Selection::Any);
+        assert_eq!(third.len(), 3);
+    }
+
+    #[test]
+    fn view_cache_rotates_rings() {
+        let mut topology = topology();
+        topology.subscribe_topic(topic(1)).unwrap();
+        let peers: Vec<ed25519::PublicKey> = (1..5)
+            .map(|seed| {
+                let key = secret_key(seed);
+                topology.add_peer(Profile::from_gossip(gossip_with(&key, 1, &[topic(1)])));
+                key.public_key()
+            })
+            .collect();
+        // two nodes on each side of the ring, so the ring has to rotate
+        topology.view_layers[0].restore(layer::LayerState::Rings(vec![(
+            topic(1),
+            peers[..2].to_vec(),
+            peers[2..].to_vec(),
+        )]));
+        topology.invalidate_view_cache();
+
+        let selection = Selection::Topic { topic: topic(1) };
+        let ids = |topology: &mut Topology| -> Vec<ed25519::PublicKey> {
+            topology
+                .view(None, selection)
+                .iter()
+                .map(|profile| profile.id())
+                .collect()
+        };
+
+        let first = ids(&mut topology);
+        assert!(topology.view_cache.contains(&(None, selection)));
+        let second = ids(&mut topology);
+
+        // the rings come first and pick the other neighbors, the other
+        // layers (served from the cache) still add the same peers
+        assert_ne!(first[..2], second[..2]);
+        assert_eq!(
+            first.iter().collect::<BTreeSet<_>>(),
+            second.iter().collect::<BTreeSet<_>>()
+        );
+        assert_eq!(ids(&mut topology)[..2], first[..2]);
+    }
+
+    #[test]
+    fn view_cache_bounded() {
+        let mut topology = topology();
+        topology.import_gossip(gossip_at(&secret_key(1), 1));
+
+        for seed in 0..(Topology::MAX_CACHED_VIEWS as u16 + 10) {
+            let mut bytes = [0; Topic::SIZE];
+            bytes[..2].copy_from_slice(&seed.to_be_bytes());
+            topology.view(
+                None,
+                Selection::Topic {
+                    topic: Topic::new(bytes),
+                },
+            );
+        }
+
+        assert_eq!(topology.view_cache.len(), Topology::MAX_CACHED_VIEWS);
+    }
+
+    #[test]
+    fn update_local_address() {
+        let mut topology = topology();
+        let address: SocketAddr = "[::1]:4321".parse().unwrap();
+
+        topology.update_local_address(address, &secret_key(0));
+
+        assert_eq!(topology.local_address(), address);
+        assert_eq!(topology.self_gossip().address(), address);
+        GossipSlice::try_from_slice(topology.self_gossip().as_ref()).unwrap();
+    }
+
+    #[test]
+    fn gossips_requested_by() {
+        let mut topology = topology();
+        topology.subscribe_topic(topic(1)).unwrap();
+        for seed in 1..5 {
+            topology.import_gossip(gossip_with(&secret_key(seed), 1, &[topic(1)]));
+        }
+
+        let requester = secret_key(1).public_key();
+        let known: HashSet<_> = vec![secret_key(2).public_key()].into_iter().collect();
+
+        let gossips = topology.gossips_requested_by(&requester, &known);
+        let ids: BTreeSet<_> = gossips.iter().map(|gossip| gossip.id()).collect();
+        let expected: BTreeSet<_> = vec![
+            secret_key(0).public_key(),
+            secret_key(3).public_key(),
+            secret_key(4).public_key(),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(ids, expected);
+    }
+
+    #[test]
+    fn iter_peers() {
+        let mut topology = topology();
+        for seed in 1..5 {
+            topology.import_gossip(gossip_at(&secret_key(seed), 1));
+        }
+        topology.promote_peer(&secret_key(1).public_key());
+        topology.remove_peer(&secret_key(2).public_key());
+
+        let topology = &topology;
+        let ids: BTreeSet<_> = topology.iter_peers().map(|profile| profile.id()).collect();
+        let expected: BTreeSet<_> = (1..5).map(|seed| secret_key(seed).public_key()).collect();
+        assert_eq!(ids, expected);
+    }
+
+    #[test]
+    fn view_ordered() {
+        let mut topology = topology();
+        topology.subscribe_topic(topic(1)).unwrap();
+        topology.subscribe_topic(topic(2)).unwrap();
+        topology.update_profile_subscriptions(&secret_key(0));
+
+        topology.import_gossip(gossip_with(&secret_key(1), 1, &[topic(1), topic(2)]));
+        topology.import_gossip(gossip_with(&secret_key(2), 3, &[topic(1)]));
+        topology.import_gossip(gossip_with(&secret_key(3), 2, &[]));
+
+        let ids = |view: Vec<Arc<Profile>>| -> Vec<ed25519::PublicKey> {
+            view.iter().map(|profile| profile.id()).collect()
+        };
+        let keys = |seeds: &[u8]| -> Vec<ed25519::PublicKey> {
+            seeds
+                .iter()
+                .map(|seed| secret_key(*seed).public_key())
+                .collect()
+        };
+
+        assert_eq!(
+            ids(topology.view_ordered(Selection::Any, ViewOrdering::FreshnessDesc)),
+            keys(&[2, 3, 1])
+        );
+        assert_eq!(
+            ids(topology.view_ordered(Selection::Any, ViewOrdering::ProximityDesc)),
+            keys(&[1, 2, 3])
+        );
+        assert_eq!(
+            ids(topology.view_ordered(Selection::Any, ViewOrdering::LayerPriority)),
+            ids(topology.view(None, Selection::Any))
+        );
+    }
+
+    #[test]
+    fn join() {
+        let address: SocketAddr = "127.0.0.1:1234".parse().unwrap();
+        let seeds = vec![
+            gossip_with(&secret_key(1), 1, &[topic(1)]),
+            gossip_with(&secret_key(2), 1, &[topic(2)]),
+        ];
+
+        let (topology, gossips) =
+            Topology::join(address, &secret_key(0), vec![topic(1), topic(3)], seeds);
+
+        assert!(topology.contains_peer(&secret_key(1).public_key()));
+        assert!(topology.contains_peer(&secret_key(2).public_key()));
+
+        let advertised: BTreeSet<Topic> = topology
+            .self_gossip()
+            .subscriptions()
+            .iter_topics()
+            .collect();
+        let expected: BTreeSet<Topic> = vec![topic(1), topic(3)].into_iter().collect();
+        assert_eq!(advertised, expected);
+
+        assert_eq!(gossips.len(), 1);
+        assert_eq!(gossips[0].id(), secret_key(0).public_key());
+        GossipSlice::try_from_slice(gossips[0].as_ref()).unwrap();
+    }
+
+    #[test]
+    fn topic_last_used() {
+        let mut topology = topology();
+        assert_eq!(topology.topic_last_used(&topic(1)), None);
+
+        topology.observe_message_at(topic(1), Time::from(1));
+        assert_eq!(topology.topic_last_used(&topic(1)), Some(Time::from(1)));
+
+        topology.observe_message(topic(1));
+        assert!(topology.topic_last_used(&topic(1)).unwrap() > Time::from(1));
+        assert_eq!(topology.topic_last_used(&topic(2)), None);
+    }
+
+    #[test]
+    fn gossips_for_includes_recipient_topics() {
+        let address: SocketAddr = "127.0.0.1:1234".parse().unwrap();
+        let mut topology = Topology::new_with(address, &secret_key(0), RingsOnly);
+
+        let recipient = secret_key(1).public_key();
+        let subscriber = secret_key(2).public_key();
+        topology.import_gossip(gossip_with(&secret_key(1), 1, &[topic(1)]));
+        topology.import_gossip(gossip_with(&secret_key(2), 1, &[topic(1)]));
+        topology.import_gossip(gossip_with(&secret_key(3), 1, &[topic(2)]));
+
+        // we are not interested in the topic so it is not in our view
+        assert!(topology.view(None, Selection::Any).is_empty());
+
+        let ids: BTreeSet<_> = topology
+            .gossips_for(&recipient)
+            .iter()
+            .map(|gossip| gossip.id())
+            .collect();
+        let expected: BTreeSet<_> = vec![subscriber, secret_key(0).public_key()]
+            .into_iter()
+            .collect();
+        assert_eq!(ids, expected);
+    }
+
+    #[test]
+    fn reproducible_view_and_gossips() {
+        let build = || {
+            let mut topology = topology();
+            topology.subscribe_topic(topic(1)).unwrap();
+            for seed in 1..12 {
+                let topics = if seed % 3 == 0 {
+                    vec![topic(1)]
+                } else {
+                    vec![topic(2)]
+                };
+                topology.add_peer(Profile::from_gossip(gossip_with(
+                    &secret_key(seed),
+                    seed as u32,
+                    &topics,
+                )));
+            }
+            topology
+        };
+
+        let mut first = build();
+        let mut second = build();
+
+        let ids = |topology: &mut Topology| -> Vec<ed25519::PublicKey> {
+            topology
+                .view(None, Selection::Any)
+                .iter()
+                .map(|profile| profile.id())
+                .collect()
+        };
+        assert_eq!(ids(&mut first), ids(&mut second));
+
+        // our own gossip is signed with the time each topology was created
+        // so only the selected peers are compared
+        let recipient = secret_key(3).public_key();
+        let gossip_ids = |topology: &mut Topology| -> Vec<ed25519::PublicKey> {
+            topology
+                .gossips_for(&recipient)
+                .iter()
+                .map(|gossip| gossip.id())
+                .collect()
+        };
+        assert_eq!(gossip_ids(&mut first), gossip_ids(&mut second));
+    }
+
+    #[test]
+    fn ban_peer() {
+        let mut topology = topology();
+        let peer = secret_key(1);
+        let id = peer.public_key();
+
+        assert!(topology.add_peer(Profile::from_gossip(gossip_at(&peer, 10))));
+        topology.ban_peer(&id);
+        assert!(topology.is_banned(&id));
+        assert!(!topology.contains_peer(&id));
+
+        assert!(!topology.add_peer(Profile::from_gossip(gossip_at(&peer, 20))));
+        assert!(matches!(
+            topology.import_gossip(gossip_at(&peer, 30)),
+            ImportOutcome::Unchanged
+        ));
+        assert!(!topology.contains_peer(&id));
+
+        assert!(topology.unban_peer(&id));
+        assert!(!topology.unban_peer(&id));
+        assert!(topology.add_peer(Profile::from_gossip(gossip_at(&peer, 40))));
+        assert!(topology.contains_peer(&id));
+    }
+
+    #[test]
+    fn view_addresses() {
+        let mut topology = topology();
+        topology.subscribe_topic(topic(1)).unwrap();
+        for seed in 1..6 {
+            topology.add_peer(Profile::from_gossip(gossip_with(
+                &secret_key(seed),
+                10,
+                &[topic(1)],
+            )));
+        }
+
+        let selection = Selection::Topic { topic: topic(1) };
+        let mut expected: Vec<SocketAddr> = Vec::new();
+        for profile in topology.view(None, selection) {
+            if !expected.contains(&profile.address()) {
+                expected.push(profile.address());
+            }
+        }
+
+        assert!(!expected.is_empty());
+        assert_eq!(topology.view_addresses(selection), expected);
+        // all the test peers share the same address
+        assert_eq!(expected.len(), 1);
+    }
+
+    #[test]
+    fn estimate_gossip_size() {
+        let mut topology = topology();
+        topology.subscribe_topic(topic(1)).unwrap();
+        for seed in 1..6 {
+            topology.add_peer(Profile::from_gossip(gossip_with(
+                &secret_key(seed),
+                10,
+                &[topic(1), topic(seed)],
+            )));
+        }
+        let mut subscriptions = Subscriptions::new();
+        subscriptions
+            .push(Subscription::new(topic(1), InterestLevel::new(1)).as_slice())
+            .unwrap();
+        let address: SocketAddr = "127.0.0.1:9876".parse().unwrap();
+        let with_metadata =
+            Gossip::new_with_metadata(address, &secret_key(6), subscriptions.as_slice(), b"app")
+                .unwrap();
+        topology.add_peer(Profile::from_gossip(with_metadata));
+
+        let recipient = secret_key(1).public_key();
+        let gossips = topology.gossips_for(&recipient);
+        assert!(gossips.iter().any(|gossip| gossip.metadata().is_some()));
+
+        let estimate = topology.estimate_gossip_size(&recipient);
+        let actual: usize = topology
+            .gossips_for(&recipient)
+            .iter()
+            .map(|gossip| gossip.as_ref().len())
+            .sum();
+
+        assert_eq!(estimate, actual);
+        assert_eq!(
+            topology.estimate_gossip_size(&secret_key(42).public_key()),
+            0
+        );
+    }
+
+    #[test]
+    fn update_promotes_sender() {
+        let mut topology = topology();
+        let sender = secret_key(1);
+        let other = secret_key(2);
+        topology.import_gossip(gossip_at(&sender, 1));
+        topology.import_gossip(gossip_at(&other, 1));
+
+        // the sender's gossip is not part of the batch
+        topology.update(&sender.public_key(), vec![gossip_at(&other, 2)]);
+        assert!(topology.peers().pool().contains(&sender.public_key()));
+
+        topology.update(
+            &sender.public_key(),
+            vec![gossip_at(&other, 3), gossip_at(&sender, 5)],
+        );
+        let profile = topology.peers().peek(&sender.public_key()).unwrap();
+        assert_eq!(profile.last_update(), Time::from(5));
+        assert!(topology.peers().trusted().contains(&sender.public_key()));
+        assert!(topology.peers().pool().contains(&other.public_key()));
+
+        // a tampered gossip of the sender does not promote it
+        let third = secret_key(3);
+        topology.import_gossip(gossip_at(&third, 1));
+        let mut bytes = gossip_at(&third, 2).as_ref().to_vec();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        let tampered = GossipSlice::from_slice_unchecked(&bytes).to_owned();
+        topology.update(&third.public_key(), vec![tampered]);
+        assert!(topology.peers().pool().contains(&third.public_key()));
+    }
+
+    #[test]
+    fn update_returns_new_relevant_peers() {
+        // the default layers also select the peers without a common topic
+        let mut topology = topology();
+        topology.subscribe_topic(topic(1)).unwrap();
+
+        let sender = secret_key(1);
+        topology.import_gossip(gossip_with(&sender, 1, &[topic(2)]));
+
+        // a ring keeps one predecessor and one successor: pick one peer on
+        // each side of our id
+        let ours = secret_key(0).public_key();
+        let predecessor = (2..32)
+            .map(secret_key)
+            .find(|key| key.public_key() < ours)
+            .unwrap();
+        let successor = (2..32)
+            .map(secret_key)
+            .find(|key| key.public_key() > ours)
+            .unwrap();
+        let unrelated = secret_key(42);
+
+        let mut learned: Vec<ed25519::PublicKey> = topology
+            .update(
+                &sender.public_key(),
+                vec![
+                    gossip_with(&sender, 2, &[topic(2)]),
+                    gossip_with(&predecessor, 1, &[topic(1)]),
+                    gossip_with(&successor, 1, &[topic(1)]),
+                    gossip_with(&unrelated, 1, &[topic(2)]),
+                ],
+            )
+            .iter()
+            .map(|profile| profile.id())
+            .collect();
+        learned.sort();
+
+        let mut expected = vec![predecessor.public_key(), successor.public_key()];
+        expected.sort();
+        assert_eq!(learned, expected);
+
+        // nothing new the second time
+        assert!(topology
+            .update(
+                &sender.public_key(),
+                vec![gossip_with(&predecessor, 1, &[topic(1)])]
+            )
+            .is_empty());
+    }
+
+    #[test]
+    fn peer_count_and_view_size() {
+        let mut topology = topology();
+        topology.subscribe_topic(topic(1)).unwrap();
+        assert_eq!(topology.peer_count(), 0);
+
+        for seed in 1..8 {
+            let topics = if seed % 2 == 0 {
+                vec![topic(1)]
+            } else {
+                vec![topic(2)]
+            };
+            topology.add_peer(Profile::from_gossip(gossip_with(
+                &secret_key(seed),
+                10,
+                &topics,
+            )));
+        }
+        topology.remove_peer(&secret_key(1).public_key());
+        topology.remove_peer(&secret_key(1).public_key());
+        assert_eq!(topology.peer_count(), 7);
+
+        for include_self in vec![false, true] {
+            topology.include_self(include_self);
+            for selection in vec![
+                Selection::Any,
+                Selection::Topic { topic: topic(1) },
+                Selection::Topic { topic: topic(3) },
+            ] {
+                let size = topology.view_size(selection);
+                assert_eq!(size, topology.view(None, selection).len());
+                assert_eq!(size, topology.detailed_view(selection).len());
+                // and from the cached view
+                assert_eq!(topology.view_size(selection), size);
+            }
+        }
+    }
+
+    #[test]
+    fn validate() {
+        let mut topology = topology();
+        topology.subscribe_topic(topic(1)).unwrap();
+        for seed in 1..6 {
+            topology.add_peer(Profile::from_gossip(gossip_with(
+                &secret_key(seed),
+                1,
+                &[topic(1)],
+            )));
+        }
+        topology.validate().unwrap();
+
+        let stranger = Profile::from_gossip(gossip_at(&secret_key(10), 1));
+        topology.view_layers[2].populate(&topology.profile, &stranger);
+        let known = topology.peers().pool().peek_lru().unwrap().1.clone();
+        topology.profiles.dirty.put(known.id(), Arc::clone(&known));
+        let ours = Arc::new(Profile::from_gossip(topology.profile.gossip().clone()));
+        topology.profiles.put(ours.id(), ours);
+
+        let errors = topology.validate().unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                ConsistencyError::DanglingLayerKey {
+                    layer: "poldercast::cyclon",
+                    id: stranger.id(),
+                },
+                ConsistencyError::MultiplePools { id: known.id() },
+                ConsistencyError::OwnProfileInPool,
+            ]
+        );
+    }
+
+    #[test]
+    fn ingest_rate_limit() {
+        let mut topology = topology();
+        let sender = secret_key(1).public_key();
+        let other = secret_key(2).public_key();
+        topology.set_ingest_rate_limit(3, Duration::from_secs(10));
+
+        let outcomes: Vec<bool> = (10..15)
+            .map(|seed| {
+                let outcome = topology.import_gossip_from_at(
+                    &sender,
+                    gossip_at(&secret_key(seed), 1),
+                    Time::from(100),
+                );
+                matches!(outcome, ImportOutcome::RateLimited)
+            })
+            .collect();
+        assert_eq!(outcomes, vec![false, false, false, true, true]);
+        assert_eq!(topology.peer_count(), 3);
+        assert_eq!(topology.ingest_strikes(&sender), 1);
+
+        // other senders have their own budget
+        assert!(matches!(
+            topology.import_gossip_from_at(&other, gossip_at(&secret_key(20), 1), Time::from(105)),
+            ImportOutcome::New
+        ));
+        assert_eq!(topology.ingest_strikes(&other), 0);
+
+        // the budget is restored in the next window
+        assert!(matches!(
+            topology.import_gossip_from_at(&sender, gossip_at(&secret_key(21), 1), Time::from(110)),
+            ImportOutcome::New
+        ));
+        assert_eq!(topology.ingest_strikes(&sender), 1);
+    }
+
+    #[test]
+    fn include_self() {
+        let mut topology = topology();
+        let ours = topology.self_gossip().id();
+        topology.subscribe_topic(topic(1)).unwrap();
+
+        assert!(topology.view(None, Selection::Any).is_empty());
+
+        topology.include_self(true);
+        let view = topology.view(None, Selection::Any);
+        assert_eq!(view.len(), 1);
+        assert_eq!(view[0].id(), ours);
+        assert_eq!(
+            topology
+                .view(None, Selection::Topic { topic: topic(1) })
+                .len(),
+            1
+        );
+        assert!(topology
+            .view(None, Selection::Topic { topic: topic(2) })
+            .is_empty());
+
+        topology.add_peer(Profile::from_gossip(gossip_at(&secret_key(1), 1)));
+        let view = topology.view(None, Selection::Any);
+        assert_eq!(view.len(), 2);
+        assert_eq!(view.last().unwrap().id(), ours);
+
+        topology.include_self(false);
+        assert!(topology
+            .view(None, Selection::Any)
+            .iter()
+            .all(|profile| profile.id() != ours));
+    }
+
+    #[test]
+    fn gossip_max_age() {
+        for threshold in vec![0, 16] {
+            let mut topology = topology();
+            topology.set_small_pool_threshold(threshold);
+            let recipient = secret_key(1);
+            topology.add_peer(Profile::from_gossip(gossip_at(&recipient, 1_000)));
+            for seed in 2..5 {
+                topology.add_peer(Profile::from_gossip(gossip_at(&secret_key(seed), 100)));
+            }
+            for seed in 5..8 {
+                topology.add_peer(Profile::from_gossip(gossip_at(&secret_key(seed), 950)));
+            }
+
+            let now = Time::from(1_000);
+            let all = topology.gossips_for_at(&recipient.public_key(), now);
+
+            topology.set_gossip_max_age(Duration::from_secs(60));
+            let fresh = topology.gossips_for_at(&recipient.public_key(), now);
+
+            let ours = topology.self_gossip().id();
+            assert!(fresh.len() < all.len());
+            assert!(fresh.iter().any(|gossip| gossip.id() == ours));
+            assert!(fresh
+                .iter()
+                .filter(|gossip| gossip.id() != ours)
+                .all(|gossip| gossip.time() == Time::from(950)));
+            assert_eq!(
+                fresh.len(),
+                all.iter()
+                    .filter(|gossip| gossip.id() == ours || gossip.time() == Time::from(950))
+                    .count()
+            );
+        }
+    }
+
+    #[test]
+    fn peer_profile() {
+        let mut topology = topology();
+        let peer = secret_key(1);
+        topology.import_gossip(gossip_at(&peer, 10));
+
+        let profile = topology.peer_profile(&peer.public_key()).unwrap();
+
+        topology.import_gossip(gossip_at(&peer, 20));
+        topology.forget_peer(&peer.public_key());
+        assert!(topology.peer_profile(&peer.public_key()).is_none());
+
+        assert_eq!(profile.id(), peer.public_key());
+        assert_eq!(profile.last_update(), Time::from(10));
+    }
+
+    #[test]
+    fn layer_overlap() {
+        let mut topology = topology();
+        topology.subscribe_topic(topic(1)).unwrap();
+
+        let report = topology.layer_overlap(Selection::Any);
+        assert_eq!(report.layers.len(), 3);
+        assert!(report.layers.iter().all(|(_, count)| *count == 0));
+        assert_eq!(
+            report.overlap("poldercast::rings", "poldercast::vicinity"),
+            Some(0)
+        );
+
+        topology.add_peer(Profile::from_gossip(gossip_with(
+            &secret_key(1),
+            1,
+            &[topic(1)],
+        )));
+        topology.add_peer(Profile::from_gossip(gossip_with(
+            &secret_key(2),
+            1,
+            &[topic(2)],
+        )));
+
+        let report = topology.layer_overlap(Selection::Any);
+        assert_eq!(
+            report.layers,
+            vec![
+                ("poldercast::rings", 1),
+                ("poldercast::vicinity", 2),
+                ("poldercast::cyclon", 2),
+            ]
+        );
+        assert_eq!(
+            report.overlap("poldercast::rings", "poldercast::vicinity"),
+            Some(1)
+        );
+        assert_eq!(
+            report.overlap("poldercast::vicinity", "poldercast::rings"),
+            Some(1)
+        );
+        assert_eq!(
+            report.overlap("poldercast::vicinity", "poldercast::cyclon"),
+            Some(2)
+        );
+        assert_eq!(report.overlap("poldercast::rings", "unknown"), None);
+    }
+
+    #[test]
+    fn fork() {
+        let ids = |topology: &mut Topology| -> Vec<ed25519::PublicKey> {
+            topology
+                .view(None, Selection::Any)
+                .iter()
+                .map(|profile| profile.id())
+                .collect()
+        };
+        let peers = |topology: &Topology| -> BTreeSet<ed25519::PublicKey> {
+            topology
+                .peers()
+                .iter()
+                .map(|profile| profile.id())
+                .collect()
+        };
+
+        let mut original = topology();
+        original.subscribe_topic(topic(1)).unwrap();
+        for seed in 1..6 {
+            original.add_peer(Profile::from_gossip(gossip_with(
+                &secret_key(seed),
+                1,
+                &[topic(1)],
+            )));
+        }
+        original.promote_peer(&secret_key(1).public_key());
+
+        let mut fork = original.fork().unwrap();
+        let original_peers = peers(&original);
+        let original_view = ids(&mut original);
+        assert_eq!(peers(&fork), original_peers);
+        assert_eq!(ids(&mut fork), original_view);
+        assert!(fork.peers().trusted().contains(&secret_key(1).public_key()));
+
+        fork.forget_peer(&secret_key(1).public_key());
+        fork.add_peer(Profile::from_gossip(gossip_with(
+            &secret_key(10),
+            1,
+            &[topic(1)],
+        )));
+        fork.unsubscribe_topic(&topic(1));
+        assert_ne!(peers(&fork), original_peers);
+
+        assert_eq!(peers(&original), original_peers);
+        assert!(original.is_subscribed(&topic(1)));
+        assert!(original
+            .peers()
+            .trusted()
+            .contains(&secret_key(1).public_key()));
+        let view = ids(&mut original);
+        assert_eq!(
+            view.iter().collect::<BTreeSet<_>>(),
+            original_view.iter().collect::<BTreeSet<_>>()
+        );
+
+        struct Unforkable;
+        impl Layer for Unforkable {
+            fn name(&self) -> &'static str {
+                "unforkable"
+            }
+            fn view(&mut self, _: &mut ViewBuilder) {}
+            fn remove(&mut self, _: &ed25519::PublicKey) {}
+            fn reset(&mut self) {}
+            fn subscribe(&mut self, _: Topic) {}
+            fn unsubscribe(&mut self, _: &Topic) {}
+            fn subscriptions(&self, _: &mut crate::PriorityMap<crate::InterestLevel, Topic>) {}
+            fn populate(&mut self, _: &Profile, _: &Profile) {}
+        }
+        struct UnforkableBuilder;
+        impl LayerBuilder for UnforkableBuilder {
+            fn build_for_view(&self) -> Vec<Box<dyn Layer>> {
+                vec![Box::new(Unforkable)]
+            }
+            fn build_for_gossip(&self) -> Vec<Box<dyn Layer>> {
+                Vec::new()
+            }
+        }
+        let address: SocketAddr = "127.0.0.1:1234".parse().unwrap();
+        let topology = Topology::new_with(address, &secret_key(0), UnforkableBuilder);
+        assert!(topology.fork().is_none());
+    }
+
+    #[test]
+    fn subscribe_topic_twice() {
+        let mut topology = topology();
+
+        assert!(topology.subscribe_topic(topic(1)).unwrap());
+        assert!(!topology.subscribe_topic(topic(1)).unwrap());
+        assert!(topology.is_subscribed(&topic(1)));
+
+        topology.unsubscribe_topic(&topic(1));
+        assert!(topology.subscribe_topic(topic(1)).unwrap());
+    }
+
+    #[test]
+    fn subscribe_topic_cap() {
+        let mut topology = topology();
+
+        let topic_n = |n: usize| {
+            let mut bytes = [0; Topic::SIZE];
+            bytes[..8].copy_from_slice(&(n as u64).to_be_bytes());
+            Topic::new(bytes)
+        };
+
+        for n in 0..Subscriptions::MAX_NUM_SUBSCRIPTIONS {
+            topology.subscribe_topic(topic_n(n)).unwrap();
+        }
+        // subscribing again to a topic is still fine
+        assert!(!topology.subscribe_topic(topic_n(0)).unwrap());
+
+        let extra = topic_n(Subscriptions::MAX_NUM_SUBSCRIPTIONS);
+        assert!(matches!(
+            topology.subscribe_topic(extra),
+            Err(SubscriptionError::MaxSubscriptionReached)
+        ));
+
+        topology.unsubscribe_topic(&topic_n(0));
+        topology.subscribe_topic(extra).unwrap();
+    }
+
+    #[test]
+    fn view_with_fallback() {
+        let address: SocketAddr = "127.0.0.1:1234".parse().unwrap();
+        let mut topology = Topology::new_with(address, &secret_key(0), RingsOnly);
+        topology.subscribe_topic(topic(1)).unwrap();
+        topology.subscribe_topic(topic(2)).unwrap();
+
+        for seed in 1..4 {
+            topology.add_peer(Profile::from_gossip(gossip_with(
+                &secret_key(seed),
+                1,
+                &[topic(2)],
+            )));
+        }
+
+        // nobody is subscribed to topic 1
+        let selection = Selection::Topic { topic: topic(1) };
+        assert!(topology.view(None, selection).is_empty());
+
+        let view = topology.view_with_fallback(selection, Selection::Any, 1);
+        assert_eq!(view.len(), 1);
+        assert!(view[0].interest_in(&topic(2)).is_some());
+
+        // there are subscribers for topic 2, no fallback needed
+        let selection = Selection::Topic { topic: topic(2) };
+        let expected: Vec<_> = topology
+            .view(None, selection)
+            .iter()
+            .map(|profile| profile.id())
+            .collect();
+        assert!(!expected.is_empty());
+        let view: Vec<_> = topology
+            .view_with_fallback(selection, Selection::Any, 1)
+            .iter()
+            .map(|profile| profile.id())
+            .collect();
+        assert_eq!(view, expected);
+    }
+
+    #[test]
+    fn seed_dial_order() {
+        let seed_at = |seed: u8, port: u16, topics: &[Topic]| {
+            let address = SocketAddr::new("127.0.0.1".parse().unwrap(), port);
+            let mut subscriptions = Subscriptions::new();
+            for topic in topics {
+                let subscription = Subscription::new(*topic, InterestLevel::new(1));
+                subscriptions.push(subscription.as_slice()).unwrap();
+            }
+            Gossip::new(address, &secret_key(seed), subscriptions.as_slice())
+        };
+
+        let address: SocketAddr = "127.0.0.1:1234".parse().unwrap();
+        let (topology, _) = Topology::join(
+            address,
+            &secret_key(0),
+            vec![topic(1), topic(2)],
+            vec![
+                seed_at(1, 1001, &[topic(3)]),
+                seed_at(2, 1002, &[topic(1), topic(2)]),
+                seed_at(3, 1003, &[topic(1)]),
+            ],
+        );
+
+        let ports: Vec<u16> = topology
+            .seed_dial_order()
+            .iter()
+            .map(|address| address.port())
+            .collect();
+        assert_eq!(ports, vec![1002, 1003, 1001]);
+    }
+
+    #[test]
+    fn address_preference() {
+        let mut topology = topology();
+        for seed in 1..7 {
+            let address: SocketAddr = if seed % 2 == 0 {
+                format!("127.0.0.1:{}", 1000 + seed as u16).parse().unwrap()
+            } else {
+                format!("[::1]:{}", 1000 + seed as u16).parse().unwrap()
+            };
+            let gossip = Gossip::new(address, &secret_key(seed), Subscriptions::new().as_slice());
+            topology.add_peer(Profile::from_gossip(gossip));
+        }
+
+        let families = |topology: &mut Topology| -> Vec<bool> {
+            topology
+                .view_addresses(Selection::Any)
+                .iter()
+                .map(|address| address.is_ipv6())
+                .collect()
+        };
+
+        topology.set_address_preference(AddressPreference::Ipv6First);
+        assert_eq!(
+            families(&mut topology),
+            vec![true, true, true, false, false, false]
+        );
+
+        topology.set_address_preference(AddressPreference::Ipv4First);
+        assert_eq!(
+            families(&mut topology),
+            vec![false, false, false, true, true, true]
+        );
+
+        topology.set_address_preference(AddressPreference::NoPreference);
+        assert_eq!(topology.view_addresses(Selection::Any).len(), 6);
+    }
+
+    #[test]
+    fn targeted_view_cache_invalidation() {
+        struct RendezvousOnly;
+        impl LayerBuilder for RendezvousOnly {
+            fn build_for_view(&self) -> Vec<Box<dyn Layer>> {
+                vec![Box::new(layer::Rendezvous::new(4))]
+            }
+            fn build_for_gossip(&self) -> Vec<Box<dyn Layer>> {
+                vec![Box::new(layer::Rendezvous::new(4))]
+            }
+        }
+
+        let address: SocketAddr = "127.0.0.1:1234".parse().unwrap();
+        let mut topology = Topology::new_with(address, &secret_key(0), RendezvousOnly);
+        topology.subscribe_topic(topic(1)).unwrap();
+        topology.subscribe_topic(topic(2)).unwrap();
+
+        topology.import_gossip(gossip_with(&secret_key(1), 1, &[topic(1)]));
+        topology.import_gossip(gossip_with(&secret_key(2), 1, &[topic(2)]));
+
+        let topic1 = (None, Selection::Topic { topic: topic(1) });
+        let topic2 = (None, Selection::Topic { topic: topic(2) });
+        let any = (None, Selection::Any);
+
+        topology.view(None, topic1.1);
+        topology.view(None, topic2.1);
+        topology.view(None, any.1);
+
+        topology.import_gossip(gossip_with(&secret_key(3), 1, &[topic(1)]));
+        assert!(!topology.view_cache.contains(&topic1));
+        assert!(topology.view_cache.contains(&topic2));
+        assert!(!topology.view_cache.contains(&any));
+
+        // the peer moved from topic 1 to topic 2: both are affected
+        topology.view(None, topic1.1);
+        topology.import_gossip(gossip_with(&secret_key(1), 2, &[topic(2)]));
+        assert!(!topology.view_cache.contains(&topic1));
+        assert!(!topology.view_cache.contains(&topic2));
+
+        // the default layers are not topic scoped (or not cached): all the
+        // views are invalidated
+        let mut default = Topology::new(address, &secret_key(0));
+        default.subscribe_topic(topic(2)).unwrap();
+        default.view(None, topic2.1);
+        assert!(default.view_cache.contains(&topic2));
+        default.import_gossip(gossip_with(&secret_key(3), 1, &[topic(1)]));
+        assert!(!default.view_cache.contains(&topic2));
+
+        // only the contributions of the layers that are not topic scoped
+        // are dropped
+        struct RendezvousAndCyclon;
+        impl LayerBuilder for RendezvousAndCyclon {
+            fn build_for_view(&self) -> Vec<Box<dyn Layer>> {
+                vec![
+                    Box::new(layer::Rendezvous::new(4)),
+                    Box::new(layer::Cyclon::new(4)),
+                ]
+            }
+            fn build_for_gossip(&self) -> Vec<Box<dyn Layer>> {
+                vec![Box::new(layer::Cyclon::new(4))]
+            }
+        }
+        let mut mixed = Topology::new_with(address, &secret_key(0), RendezvousAndCyclon);
+        mixed.subscribe_topic(topic(1)).unwrap();
+        mixed.subscribe_topic(topic(2)).unwrap();
+        mixed.import_gossip(gossip_with(&secret_key(1), 1, &[topic(2)]));
+        mixed.view(None, topic1.1);
+        mixed.view(None, topic2.1);
+
+        mixed.import_gossip(gossip_with(&secret_key(3), 1, &[topic(1)]));
+        assert!(!mixed.view_cache.contains(&topic1));
+        let cached = mixed.view_cache.peek(&topic2).unwrap();
+        assert!(cached[0].is_some());
+        assert!(cached[1].is_none());
+
+        // subscribing is targeted as well
+        mixed.view(None, topic1.1);
+        mixed.subscribe_topic(topic(3)).unwrap();
+        assert!(mixed.view_cache.peek(&topic1).unwrap()[0].is_some());
+        assert!(mixed.view_cache.peek(&topic2).unwrap()[0].is_some());
+
+        let view: Vec<ed25519::PublicKey> = mixed
+            .view(None, topic2.1)
+            .iter()
+            .map(|profile| profile.id())
+            .collect();
+        assert_eq!(
+            view,
+            vec![secret_key(1).public_key(), secret_key(3).public_key()]
+        );
+    }
+
+    #[test]
+    fn merge_peers_from() {
+        let mut lan = topology();
+        let address: SocketAddr = "127.0.0.1:4321".parse().unwrap();
+        let mut wan = Topology::new(address, &secret_key(0));
+
+        lan.import_gossip(gossip_at(&secret_key(1), 10));
+        lan.import_gossip(gossip_at(&secret_key(2), 30));
+        wan.import_gossip(gossip_at(&secret_key(2), 20));
+        wan.import_gossip(gossip_at(&secret_key(3), 10));
+        wan.import_gossip(gossip_at(&secret_key(4), 10));
+
+        let lan_time = lan.get(&secret_key(2).public_key()).unwrap().last_update();
+        lan.merge_peers_from(&wan);
+        assert_eq!(lan.peer_count(), 4);
+        assert_eq!(
+            lan.get(&secret_key(2).public_key()).unwrap().last_update(),
+            lan_time
+        );
+
+        wan.merge_peers_from(&lan);
+        assert_eq!(wan.peer_count(), 4);
+        assert_eq!(
+            wan.get(&secret_key(2).public_key()).unwrap().last_update(),
+            lan_time
+        );
+    }
+
+    #[test]
+    fn merge_peers_from_skips_dirty_peers() {
+        let mut lan = topology();
+        let address: SocketAddr = "127.0.0.1:4321".parse().unwrap();
+        let mut wan = Topology::new(address, &secret_key(5));
+
+        let demoted = secret_key(3).public_key();
+        wan.import_gossip(gossip_at(&secret_key(2), 10));
+        wan.import_gossip(gossip_at(&secret_key(3), 10));
+        wan.remove_peer(&demoted);
+        assert!(wan.profiles.dirty().contains(&demoted));
+
+        lan.merge_peers_from(&wan);
+        assert!(lan.get(&secret_key(2).public_key()).is_some());
+        assert!(lan.get(&secret_key(5).public_key()).is_some());
+        assert!(lan.get(&demoted).is_none());
+        assert_eq!(lan.peer_count(), 2);
+    }
+
+    #[test]
+    fn topic_subscriber_counts() {
+        let mut topology = topology();
+        topology.import_gossip(gossip_with(&secret_key(1), 1, &[topic(1), topic(2)]));
+        topology.import_gossip(gossip_with(&secret_key(2), 1, &[topic(2), topic(3)]));
+        topology.import_gossip(gossip_with(&secret_key(3), 1, &[topic(2)]));
+        topology.import_gossip(gossip_at(&secret_key(4), 1));
+
+        let counts: Vec<(Topic, usize)> = topology.topic_subscriber_counts().into_iter().collect();
+        assert_eq!(counts, vec![(topic(1), 1), (topic(2), 3), (topic(3), 1)]);
+
+        topology.remove_peer(&secret_key(3).public_key());
+        let counts: Vec<(Topic, usize)> = topology.topic_subscriber_counts().into_iter().collect();
+        assert_eq!(counts, vec![(topic(1), 1), (topic(2), 2), (topic(3), 1)]);
+    }
+
+    #[test]
+    fn ring_neighbors() {
+        let address: SocketAddr = "127.0.0.1:1234".parse().unwrap();
+        // roughly in the middle of the ids of the other peers
+        let ours = secret_key(4);
+        let mut topology = Topology::new_with(address, &ours, RingsOnly);
+        let ours = ours.public_key();
+
+        assert_eq!(topology.ring_neighbors(&topic(1)), RingNeighbors::default());
+
+        topology.subscribe_topic(topic(1)).unwrap();
+        for seed in (0..10).filter(|seed| *seed != 4) {
+            topology.import_gossip(gossip_with(&secret_key(seed), 1, &[topic(1)]));
+        }
+        topology.import_gossip(gossip_with(&secret_key(10), 1, &[topic(2)]));
+
+        let neighbors = topology.ring_neighbors(&topic(1));
+        assert!(!neighbors.predecessors.is_empty());
+        assert!(!neighbors.successors.is_empty());
+        assert!(neighbors.predecessors.iter().all(|id| id < &ours));
+        assert!(neighbors.successors.iter().all(|id| id > &ours));
+        assert!(neighbors.predecessors.len() + neighbors.successors.len() <= 4);
+
+        assert_eq!(topology.ring_neighbors(&topic(2)), RingNeighbors::default());
+    }
+
+    #[test]
+    fn gossips_for_no_duplicates() {
+        for threshold in vec![0, 16] {
+            let mut topology = topology();
+            topology.set_small_pool_threshold(threshold);
+            topology.subscribe_topic(topic(1)).unwrap();
+
+            for seed in 1..6 {
+                topology.add_peer(Profile::from_gossip(gossip_with(
+                    &secret_key(seed),
+                    1,
+                    &[topic(1)],
+                )));
+            }
+            // a peer echoed our own gossip back
+            topology.add_peer(Profile::from_gossip(gossip_with(
+                &secret_key(0),
+                1,
+                &[topic(1)],
+            )));
+
+            let ours = topology.self_profile().id();
+            let gossips = topology.gossips_for(&secret_key(1).public_key());
+            let ids: HashSet<ed25519::PublicKey> = gossips.iter().map(|g| g.id()).collect();
+
+            assert_eq!(ids.len(), gossips.len());
+            assert_eq!(gossips.iter().filter(|g| g.id() == ours).count(), 1);
+        }
+    }
+
+    #[test]
+    fn diverse_view() {
+        let mut topology = topology();
+        let addresses = [
+            "10.0.0.1:1000",
+            "10.0.0.2:1000",
+            "10.0.0.3:1000",
+            "10.0.1.1:1000",
+            "[2001:db8::1]:1000",
+            "[2001:db8::2]:1000",
+            "[2001:db9::1]:1000",
+        ];
+        for (seed, address) in addresses.iter().enumerate() {
+            let address: SocketAddr = address.parse().unwrap();
+            let id = secret_key(seed as u8 + 1);
+            let gossip = Gossip::new(address, &id, Subscriptions::new().as_slice());
+            topology.add_peer(Profile::from_gossip(gossip));
+        }
+
+        assert_eq!(topology.view(None, Selection::Any).len(), addresses.len());
+
+        let view = topology.diverse_view(Selection::Any, 1);
+        let mut subnets: Vec<_> = view.iter().map(|p| subnet(p.address())).collect();
+        assert_eq!(subnets.len(), 4);
+        subnets.sort_unstable();
+        subnets.dedup();
+        assert_eq!(subnets.len(), 4);
+
+        let view = topology.diverse_view(Selection::Any, 2);
+        assert_eq!(view.len(), 6);
+    }
+
+    #[test]
+    fn suggested_topics() {
+        let mut topology = topology();
+        topology.subscribe_topic(topic(1)).unwrap();
+        assert!(topology.is_subscribed(&topic(1)));
+        assert!(!topology.is_subscribed(&topic(2)));
+
+        topology.import_gossip(gossip_with(&secret_key(1), 1, &[topic(1), topic(2)]));
+        topology.import_gossip(gossip_with(&secret_key(2), 1, &[topic(1), topic(2)]));
+        topology.import_gossip(gossip_with(&secret_key(3), 1, &[topic(1), topic(3)]));
+
+        assert_eq!(topology.suggested_topics(3), Vec::<Topic>::new());
+
+        topology.import_gossip(gossip_with(&secret_key(4), 1, &[topic(2)]));
+        assert_eq!(topology.suggested_topics(3), vec![topic(2)]);
+        assert_eq!(topology.suggested_topics(1), vec![topic(2), topic(3)]);
+
+        topology.remove_peer(&secret_key(4).public_key());
+        topology.remove_peer(&secret_key(3).public_key());
+        assert_eq!(topology.suggested_topics(3), Vec::<Topic>::new());
+        assert_eq!(topology.suggested_topics(1), vec![topic(2)]);
     }
 }